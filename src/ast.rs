@@ -2,41 +2,77 @@
 // (doc: https://craftinginterpreters.com/appendix-i.html)
 // a Lox program is a series of declarations:
 //   program -> declaration* EOF ;
-//   declaration -> classDecl | funDecl | varDecl | statement ;
+//   declaration -> classDecl | traitDecl | funDecl | varDecl | importDecl | statement ;
 //
-//   classDecl -> "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+//   classDecl -> "class" IDENTIFIER ( "<" IDENTIFIER )? ( "with" IDENTIFIER ( "," IDENTIFIER )* )? "{" member* "}" ;
+//   member -> function | IDENTIFIER block | "static" function | "static" IDENTIFIER "=" expression ";" ;
+//   traitDecl -> "trait" IDENTIFIER "{" function* "}" ;
 //   funDecl -> "fun" function ;
-//   varDecl -> "var" IDENTIFIER ( "=" expression )? ";" ;
-//   statement -> exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt | block ;
+//   varDecl -> "var" IDENTIFIER ( ":" type )? ( "=" expression )? ";"
+//            | "const" IDENTIFIER ( ":" type )? "=" expression ";"
+//            | ( "var" | "const" ) destructure "=" expression ";" ;
+//   destructure -> "[" ( element ( "," element )* )? "]" | "{" ( IDENTIFIER ( "," IDENTIFIER )* )? "}" ;
+//   element -> "..."? IDENTIFIER ;
+//   importDecl -> "import" STRING "as" IDENTIFIER ";" ;
+//   statement -> exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt
+//              | throwStmt | tryStmt | matchStmt | yieldStmt | block ;
 //
 //   exprStmt -> expression ";" ;
-//   forStmt -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+//   forStmt -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement
+//            | "for" "(" "var"? IDENTIFIER "in" expression ")" statement ;
 //   ifStmt -> "if" "(" expression ")" statement ( "else" statement )? ;
 //   printStmt -> "print" expression ";" ;
 //   returnStmt -> "return" expression? ";" ;
+//   yieldStmt -> "yield" expression? ";" ;
 //   whileStmt -> "while" "(" expression ")" statement ;
+//   throwStmt -> "throw" expression ";" ;
+//   tryStmt -> "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+//   matchStmt -> "match" "(" expression ")" "{" matchCase* "}" ;
+//   matchCase -> "case" pattern ( "," pattern )* ( "if" expression )? "=>" statement ;
 //   block -> "{" declaration* "}" ;
 //
 // expression produce values.
 // Lox uses a separate rule for each precedence level to make it explicit.
 //   expression -> assignment ;
-//   assignment -> ( call "." )? IDENTIFIER "=" assignment | logic_or ;
+//   assignment -> ( call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER )
+//                 ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" ) assignment
+//               | "[" ( target ( "," target )* )? "]" "=" assignment | conditional ;
+//   target -> call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER ;
+//   conditional -> coalesce ( "?" expression ":" conditional )? ;
+//   coalesce -> logic_or ( "??" logic_or )* ;
 //
 //   logic_or -> logic_and ( "or" logic_and )* ;
 //   logic_and -> equality ( "and" equality )* ;
 //   equality -> comparison ( ( "!=" | "==" ) comparison )* ;
-//   comparison -> term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+//   comparison -> bit_or ( ( ">" | ">=" | "<" | "<=" ) bit_or )* ;
+//   bit_or -> bit_xor ( "|" bit_xor )* ;
+//   bit_xor -> bit_and ( "^" bit_and )* ;
+//   bit_and -> shift ( "&" shift )* ;
+//   shift -> term ( ( "<<" | ">>" ) term )* ;
 //   term -> factor ( ( "-" | "+" ) factor )* ;
-//   factor -> unary ( ( "/" | "*" ) unary )* ;
+//   factor -> unary ( ( "/" | "*" | "%" | "~/" ) unary )* ;
 //
-//   unary -> ( "!" | "-" ) unary | call ;
-//   call -> primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
-//   primary -> "true" | "false" | "nil" | "this" | NUMBER | STRING | IDENTIFIER | "(" expression ")" | "super" "." IDENTIFIER ;
+//   unary -> ( "!" | "-" | "~" | "++" | "--" ) unary | postfix ;
+//   postfix -> call ( "++" | "--" )? ;
+//   call -> primary ( "(" arguments? ")" | "." IDENTIFIER | "?." IDENTIFIER | "[" expression "]" )* ;
+//   primary -> "true" | "false" | "nil" | "this" | INTEGER | BIGINT | DECIMAL | NUMBER | STRING | IDENTIFIER | "(" expression ")" | "super" "." IDENTIFIER
+//            | "[" arguments? "]" | "{" ( entry ( "," entry )* )? "}" | "Fiber" "." "yield" "(" expression? ")" ;
+//
+// patterns of a `match` case:
+//   pattern -> "_" | literal | IDENTIFIER | IDENTIFIER "{" ( field ( "," field )* )? "}"
+//            | "[" ( element ( "," element )* )? "]" ;
+//   literal -> "true" | "false" | "nil" | "-"? ( INTEGER | BIGINT | DECIMAL | NUMBER ) | STRING ;
+//   field -> IDENTIFIER ( ":" pattern )? ;
+//   element -> pattern | "..." IDENTIFIER ;
 //
 // helper rules:
-//   function -> IDENTIFIER "(" parameters? ")" block ;
-//   parameters -> IDENTIFIER ( "," IDENTIFIER )* ;
-//   arguments -> expression ( "," expression )* ;
+//   function -> IDENTIFIER "(" parameters? ")" ( ":" type )? block ;
+//   parameters -> parameter ( "," parameter )* ;
+//   parameter -> IDENTIFIER ( ":" type )? ( "=" expression )? | "..." IDENTIFIER ( ":" type )? ;
+//   type -> IDENTIFIER | "nil" | "fun" ;
+//   arguments -> argument ( "," argument )* ;
+//   argument -> "..."? expression ;
+//   entry -> expression ":" expression ;
 //
 
 use crate::token::Token;
@@ -74,7 +110,7 @@ pub enum Expr {
         paren: Token,
         args: Vec<Expr>,
     },
-    // logical and/or
+    // logical and/or, and `??`
     Logical {
         left: Box<Expr>,
         op: Token,
@@ -89,12 +125,79 @@ pub enum Expr {
         object: Box<Expr>,
         name: Token,
     },
+    // `a?.b`: nil when `a` is nil
+    OptionalGet {
+        object: Box<Expr>,
+        name: Token,
+    },
+    // a chain of calls, properties and indexes with a `?.` in it, like `a?.b.c()`:
+    // a nil before a `?.` skips the rest of the chain, which is nil.
+    OptionalChain {
+        chain: Box<Expr>,
+    },
+    // `...value` in arguments or a list literal, the elements of an iterable.
+    Spread {
+        dots: Token,
+        value: Box<Expr>,
+    },
+    // `condition ? then_expr : else_expr`
+    Conditional {
+        condition: Box<Expr>,
+        then_expr: Box<Expr>,
+        else_expr: Box<Expr>,
+    },
     // class property assignment
     Set {
         object: Box<Expr>,
         name: Token,
         value: Box<Expr>,
     },
+    // list literal: `[1, 2]`
+    List {
+        bracket: Token,
+        elements: Vec<Expr>,
+    },
+    // map literal: `{"a": 1}`
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    // `object[index]` on a list, map or string
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    // `object[index] = value`
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    // `[a, b] = [b, a]`: assign the elements of a list to the targets, in order
+    AssignList {
+        bracket: Token,
+        targets: Vec<Expr>, // Expr::Variable, Expr::Get or Expr::Index
+        value: Box<Expr>,
+    },
+    // compound assignment: `a += 1`, `a.b *= 2`
+    CompoundAssign {
+        target: Box<Expr>, // Expr::Variable, Expr::Get or Expr::Index
+        op: Token,
+        value: Box<Expr>,
+    },
+    // increment and decrement: `++a`, `a.b--`
+    Increment {
+        target: Box<Expr>, // Expr::Variable, Expr::Get or Expr::Index
+        op: Token,
+        prefix: bool, // a prefix one evaluates to the new value, a postfix one to the old value.
+    },
+    // `Fiber.yield(value)`: suspend the running fiber, it evaluates to the value of the next `resume`.
+    FiberYield {
+        keyword: Token, // `yield`
+        value: Option<Box<Expr>>,
+    },
     // super expression in inheritance
     Super {
         keyword: Token,
@@ -114,16 +217,39 @@ pub enum Stmt {
     },
     Class {
         name: Token,
-        super_class: Expr,  // Expr::Variable
-        methods: Vec<Stmt>, // Stmt::Function
+        super_class: Option<Expr>, // Expr::Variable
+        traits: Vec<Expr>,         // Expr::Variable, their methods are copied into the class
+        methods: Vec<Stmt>,        // Stmt::Function
+        // Stmt::Function without parameters, run when the property is read
+        getters: Vec<Stmt>,
+        // Stmt::Function, called on the class: `Math.square(3)`
+        static_methods: Vec<Stmt>,
+        // initialized when the class is declared
+        static_fields: Vec<(Token, Expr)>,
+        doc: Option<String>, // the `///` comment before the declaration
     },
     Expression {
         expr: Expr,
     },
+    // a set of methods classes can mix in with `with`.
+    Trait {
+        name: Token,
+        methods: Vec<Stmt>,  // Stmt::Function
+        doc: Option<String>, // the `///` comment before the declaration
+    },
+    // `for (x in iterable) body`, each iteration binds `x` in a new env.
+    ForIn {
+        name: Token,
+        keyword: Token, // `in`
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
     Function {
         name: Token,
-        params: Vec<Token>,
+        params: Vec<Param>,
+        return_type: Option<Token>, // the annotation after the parameters, only `--check` reads it
         body: Vec<Stmt>, // Vec<Stmt> 比 Stmt::Block 方便一点, 使用 Stmt::Block 运行时会创建2层 env
+        doc: Option<String>,
     },
     If {
         condition: Expr,
@@ -131,18 +257,227 @@ pub enum Stmt {
         else_branch: Option<Box<Stmt>>,
     },
     Print {
+        keyword: Token, // `print`, for the errors of a `__str__` call
         expr: Expr,
     },
     Return {
         keyword: Token,
         value: Option<Expr>,
     },
+    // a function with a `yield` in its body is a generator.
+    Yield {
+        keyword: Token,
+        value: Option<Expr>,
+    },
     Var {
         name: Token,
+        annotation: Option<Token>, // `var x: num`, only `--check` reads it
         initializer: Option<Expr>,
+        constant: bool, // `const`: can't be assigned after the declaration.
+        doc: Option<String>,
+    },
+    // `var [a, b] = pair;` or `var {name, age} = person;`
+    Destructure {
+        target: Destructure,
+        initializer: Expr,
+        constant: bool,
     },
     While {
         condition: Expr,
         body: Box<Stmt>,
     },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    Import {
+        keyword: Token,
+        path: String, // relative to the directory of the importing file.
+        name: Token,
+    },
+    Match {
+        keyword: Token,
+        value: Expr,
+        cases: Vec<MatchCase>,
+    },
+    Try {
+        body: Box<Stmt>,                          // Stmt::Block
+        catch_clause: Option<(Token, Box<Stmt>)>, // the name bound to the error and a Stmt::Block
+        finally_body: Option<Box<Stmt>>,          // Stmt::Block
+    },
+}
+
+impl Expr {
+    // a token of the expression, for the line of an error about it.
+    pub fn token(&self) -> &Token {
+        match self {
+            Expr::Literal { value } => value,
+            Expr::Grouping { expression } => expression.token(),
+            Expr::OptionalChain { chain } => chain.token(),
+            Expr::Conditional { condition, .. } => condition.token(),
+            Expr::Unary { op, .. }
+            | Expr::Binary { op, .. }
+            | Expr::Logical { op, .. }
+            | Expr::CompoundAssign { op, .. }
+            | Expr::Increment { op, .. } => op,
+            Expr::Assign { name, .. }
+            | Expr::Variable { name }
+            | Expr::Get { name, .. }
+            | Expr::OptionalGet { name, .. }
+            | Expr::Set { name, .. } => name,
+            Expr::Call { paren, .. } => paren,
+            Expr::Spread { dots, .. } => dots,
+            Expr::List { bracket, .. }
+            | Expr::Index { bracket, .. }
+            | Expr::SetIndex { bracket, .. }
+            | Expr::AssignList { bracket, .. } => bracket,
+            Expr::Map { brace, .. } => brace,
+            Expr::Super { keyword, .. }
+            | Expr::This { keyword }
+            | Expr::FiberYield { keyword, .. } => keyword,
+        }
+    }
+}
+
+impl Stmt {
+    // the token a runtime error of the statement itself is reported at, an empty block has none.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Stmt::Block { stmts } => stmts.first().and_then(Stmt::token),
+            Stmt::Try { body, .. } => body.token(),
+            Stmt::Expression { expr }
+            | Stmt::If {
+                condition: expr, ..
+            }
+            | Stmt::While {
+                condition: expr, ..
+            }
+            | Stmt::Destructure {
+                initializer: expr, ..
+            } => Some(expr.token()),
+            Stmt::Class { name, .. }
+            | Stmt::Trait { name, .. }
+            | Stmt::Function { name, .. }
+            | Stmt::Var { name, .. } => Some(name),
+            Stmt::ForIn { keyword, .. }
+            | Stmt::Print { keyword, .. }
+            | Stmt::Return { keyword, .. }
+            | Stmt::Yield { keyword, .. }
+            | Stmt::Throw { keyword, .. }
+            | Stmt::Import { keyword, .. }
+            | Stmt::Match { keyword, .. } => Some(keyword),
+        }
+    }
+
+    // whether running the statement can reach a `yield`, the ones in nested functions and classes don't count.
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            Stmt::Yield { .. } => true,
+            Stmt::Block { stmts } => stmts.iter().any(Stmt::contains_yield),
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                then_branch.contains_yield()
+                    || else_branch.as_ref().is_some_and(|b| b.contains_yield())
+            }
+            Stmt::While { body, .. } | Stmt::ForIn { body, .. } => body.contains_yield(),
+            Stmt::Match { cases, .. } => cases.iter().any(|case| case.body.contains_yield()),
+            Stmt::Try {
+                body,
+                catch_clause,
+                finally_body,
+            } => {
+                body.contains_yield()
+                    || catch_clause
+                        .as_ref()
+                        .is_some_and(|(_, b)| b.contains_yield())
+                    || finally_body.as_ref().is_some_and(|b| b.contains_yield())
+            }
+            _ => false,
+        }
+    }
+}
+
+// the names a destructuring declaration binds.
+#[derive(Clone, Debug)]
+pub enum Destructure {
+    // `[a, b, ...rest]`: the elements of a list, in order
+    List {
+        bracket: Token,
+        names: Vec<Token>,
+        rest: Option<Token>, // a list of the elements after the names
+    },
+    // `{name, age}`: the fields of an instance or the string keys of a map
+    Fields {
+        brace: Token,
+        names: Vec<Token>,
+    },
+}
+
+impl Destructure {
+    pub fn names(&self) -> Vec<&Token> {
+        match self {
+            Destructure::List { names, rest, .. } => names.iter().chain(rest).collect(),
+            Destructure::Fields { names, .. } => names.iter().collect(),
+        }
+    }
+}
+
+// `name`, `name = default` or `...name`.
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: Token,
+    pub annotation: Option<Token>, // `a: num`, only `--check` reads it
+    pub default: Option<Expr>,     // evaluated at the call, when the argument is missing
+    pub rest: bool,                // collects the remaining arguments into a list
+}
+
+// `case 1, 2 if ok => body`: the first case with a matching pattern and a truthy guard runs.
+#[derive(Clone, Debug)]
+pub struct MatchCase {
+    pub patterns: Vec<Pattern>, // alternatives
+    pub guard: Option<Expr>,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    // `_` matches anything
+    Wildcard,
+    // matches an equal value
+    Literal {
+        value: Expr, // Expr::Literal, or an Expr::Unary negating a number
+    },
+    // matches anything and binds it to the name
+    Binding {
+        name: Token,
+    },
+    // `Point { x, y: 0 }` matches an instance of the class, `x` is short for `x: x`.
+    Instance {
+        class: Expr, // Expr::Variable
+        fields: Vec<(Token, Pattern)>,
+    },
+    // `[first, second, ...rest]` matches a list whose elements match, `...rest` takes the others.
+    List {
+        bracket: Token,
+        elements: Vec<Pattern>,
+        rest: Option<Token>, // `..._` takes them without binding a name
+    },
+}
+
+impl Pattern {
+    // the first name the pattern binds, if any.
+    pub fn binding(&self) -> Option<Token> {
+        match self {
+            Pattern::Binding { name } => Some(name.clone()),
+            Pattern::Instance { fields, .. } => fields.iter().find_map(|(_, p)| p.binding()),
+            Pattern::List { elements, rest, .. } => elements
+                .iter()
+                .find_map(Pattern::binding)
+                .or_else(|| rest.clone().filter(|rest| rest.lexeme != "_")),
+            Pattern::Wildcard | Pattern::Literal { .. } => None,
+        }
+    }
 }
@@ -0,0 +1,510 @@
+use core::fmt;
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+    mem,
+};
+
+// the crate has no dependencies, so the exact numbers of Lox are implemented here:
+// - BigInt: an arbitrary-precision integer, written `10n`.
+// - Decimal: a fixed-point decimal number, written `1.20d`, it's a BigInt scaled by a power of ten.
+
+const BASE: u64 = 1_000_000_000; // every limb holds 9 decimal digits.
+const BASE_DIGITS: usize = 9;
+
+// the number of fractional digits a decimal division keeps when the result isn't exact.
+pub const DIV_SCALE: u32 = 20;
+
+// the largest exponent a decimal literal may have, `1e10000d` would be ten thousand digits already.
+const MAX_EXPONENT: i64 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>, // little-endian, no leading zero limbs, zero is an empty vec and never negative.
+}
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt {
+            negative: false,
+            limbs: Vec::new(),
+        }
+    }
+
+    pub fn from_i64(num: i64) -> BigInt {
+        let mut mag = num.unsigned_abs();
+        let mut limbs = Vec::new();
+        while mag > 0 {
+            limbs.push((mag % BASE) as u32);
+            mag /= BASE;
+        }
+        BigInt {
+            negative: num < 0,
+            limbs,
+        }
+    }
+
+    // parse the digits of a literal in the given radix, e.g. "ff" in radix 16.
+    // the scanner has already checked every char is a digit of the radix.
+    pub fn from_digits(digits: &str, radix: u32) -> BigInt {
+        let mut limbs = Vec::new();
+        for d in digits.chars().filter_map(|c| c.to_digit(radix)) {
+            mul_add_small(&mut limbs, radix, d);
+        }
+        BigInt {
+            negative: false,
+            limbs,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        // i64::MAX has 19 digits, 3 limbs.
+        if self.limbs.len() > 3 {
+            return None;
+        }
+        let mag = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0i128, |mag, &limb| mag * BASE as i128 + limb as i128);
+        i64::try_from(if self.negative { -mag } else { mag }).ok()
+    }
+
+    // the bytes of the digits.
+    pub fn size(&self) -> usize {
+        self.limbs.len() * mem::size_of::<u32>()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let mag = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0.0, |num, &limb| num * BASE as f64 + limb as f64);
+        match self.negative {
+            true => -mag,
+            false => mag,
+        }
+    }
+
+    fn from_limbs(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+        BigInt { negative, limbs }
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::from_limbs(!self.negative, self.limbs.clone())
+    }
+
+    pub fn add(&self, oth: &BigInt) -> BigInt {
+        if self.negative == oth.negative {
+            return BigInt::from_limbs(self.negative, add_limbs(&self.limbs, &oth.limbs));
+        }
+        // the signs differ: subtract the smaller magnitude from the larger one.
+        match cmp_limbs(&self.limbs, &oth.limbs) {
+            Ordering::Less => BigInt::from_limbs(oth.negative, sub_limbs(&oth.limbs, &self.limbs)),
+            _ => BigInt::from_limbs(self.negative, sub_limbs(&self.limbs, &oth.limbs)),
+        }
+    }
+
+    pub fn sub(&self, oth: &BigInt) -> BigInt {
+        self.add(&oth.neg())
+    }
+
+    pub fn mul(&self, oth: &BigInt) -> BigInt {
+        if self.is_zero() || oth.is_zero() {
+            return BigInt::zero();
+        }
+        let mut res = vec![0u64; self.limbs.len() + oth.limbs.len() + 1];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in oth.limbs.iter().enumerate() {
+                let cur = res[i + j] + a as u64 * b as u64 + carry;
+                res[i + j] = cur % BASE;
+                carry = cur / BASE;
+            }
+            let mut k = i + oth.limbs.len();
+            while carry > 0 {
+                let cur = res[k] + carry;
+                res[k] = cur % BASE;
+                carry = cur / BASE;
+                k += 1;
+            }
+        }
+        BigInt::from_limbs(
+            self.negative != oth.negative,
+            res.into_iter().map(|limb| limb as u32).collect(),
+        )
+    }
+
+    // the quotient truncated toward zero and the remainder with the sign of the dividend,
+    // like the `~/` and `%` operators on integers. `oth` must not be zero.
+    pub fn div_rem(&self, oth: &BigInt) -> (BigInt, BigInt) {
+        let mut quotient = vec![0u32; self.limbs.len()];
+        let mut rem: Vec<u32> = Vec::new();
+        for i in (0..self.limbs.len()).rev() {
+            // rem = rem * BASE + limb, then find the largest q with oth * q <= rem.
+            rem.insert(0, self.limbs[i]);
+            while rem.last() == Some(&0) {
+                rem.pop();
+            }
+            let (mut lo, mut hi) = (0u32, (BASE - 1) as u32);
+            while lo < hi {
+                let mid = lo + (hi - lo).div_ceil(2);
+                match cmp_limbs(&mul_small(&oth.limbs, mid), &rem) {
+                    Ordering::Greater => hi = mid - 1,
+                    _ => lo = mid,
+                }
+            }
+            quotient[i] = lo;
+            rem = sub_limbs(&rem, &mul_small(&oth.limbs, lo));
+        }
+        (
+            BigInt::from_limbs(self.negative != oth.negative, quotient),
+            BigInt::from_limbs(self.negative, rem),
+        )
+    }
+
+    // self * 10^exp
+    fn scale_up(&self, exp: u32) -> BigInt {
+        let mut limbs = self.limbs.clone();
+        for _ in 0..exp {
+            mul_add_small(&mut limbs, 10, 0);
+        }
+        BigInt::from_limbs(self.negative, limbs)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, oth: &Self) -> Ordering {
+        match (self.negative, oth.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_limbs(&self.limbs, &oth.limbs),
+            (true, true) => cmp_limbs(&oth.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, oth: &Self) -> Option<Ordering> {
+        Some(self.cmp(oth))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut limbs = self.limbs.iter().rev();
+        write!(f, "{}", limbs.next().unwrap())?;
+        for limb in limbs {
+            write!(f, "{:0width$}", limb, width = BASE_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+fn cmp_limbs(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let cur = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        res.push((cur % BASE) as u32);
+        carry = cur / BASE;
+    }
+    if carry > 0 {
+        res.push(carry as u32);
+    }
+    res
+}
+
+// a - b, where |a| >= |b|.
+fn sub_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut cur = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if cur < 0 {
+            cur += BASE as i64;
+            borrow = 1;
+        }
+        res.push(cur as u32);
+    }
+    while res.last() == Some(&0) {
+        res.pop();
+    }
+    res
+}
+
+fn mul_small(a: &[u32], m: u32) -> Vec<u32> {
+    let mut res = a.to_vec();
+    mul_add_small(&mut res, m, 0);
+    while res.last() == Some(&0) {
+        res.pop();
+    }
+    res
+}
+
+// limbs = limbs * m + add
+fn mul_add_small(limbs: &mut Vec<u32>, m: u32, add: u32) {
+    let mut carry = add as u64;
+    for limb in limbs.iter_mut() {
+        let cur = *limb as u64 * m as u64 + carry;
+        *limb = (cur % BASE) as u32;
+        carry = cur / BASE;
+    }
+    while carry > 0 {
+        limbs.push((carry % BASE) as u32);
+        carry /= BASE;
+    }
+}
+
+// a decimal is `unscaled * 10^-scale`, so `1.20d` is (120, 2). the scale is kept as written,
+// which is why `1.20d` prints `1.20`, and arithmetic keeps the larger scale of its operands.
+// it doesn't change the value though: `1.20d == 1.2d`, and they hash the same.
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    unscaled: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn from_bigint(num: BigInt) -> Decimal {
+        Decimal {
+            unscaled: num,
+            scale: 0,
+        }
+    }
+
+    // the exact value of a finite float, `0.1` is 0.1000000000000000055511151231257827...
+    pub fn from_f64(num: f64) -> Decimal {
+        let bits = num.to_bits();
+        let frac = bits & ((1 << 52) - 1);
+        // num = mantissa * 2^exp, a subnormal has no implicit leading bit.
+        let (mantissa, exp) = match ((bits >> 52) & 0x7ff) as i32 {
+            0 => (frac, -1074),
+            biased => (frac | 1 << 52, biased - 1075),
+        };
+        let mut limbs = BigInt::from_i64(mantissa as i64).limbs;
+        // 2^-n = 5^n * 10^-n
+        let (m, scale) = match exp >= 0 {
+            true => (2, 0),
+            false => (5, exp.unsigned_abs()),
+        };
+        for _ in 0..exp.unsigned_abs() {
+            mul_add_small(&mut limbs, m, 0);
+        }
+        Decimal {
+            unscaled: BigInt {
+                negative: num.is_sign_negative() && !limbs.is_empty(),
+                limbs,
+            },
+            scale,
+        }
+    }
+
+    // parse a literal like "1.20", "5" or "1.5e-3" (without the `d` suffix and the `_` separators).
+    // `None` when the exponent is out of range.
+    pub fn parse(literal: &str) -> Option<Decimal> {
+        let (mantissa, exp) = match literal.find(['e', 'E']) {
+            Some(idx) => (&literal[..idx], literal[idx + 1..].parse::<i64>().ok()?),
+            None => (literal, 0),
+        };
+        if exp.abs() > MAX_EXPONENT {
+            return None;
+        }
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let unscaled = BigInt::from_digits(&format!("{}{}", int, frac), 10);
+        let scale = frac.len() as i64 - exp;
+        Some(match scale >= 0 {
+            true => Decimal {
+                unscaled,
+                scale: scale as u32,
+            },
+            false => Decimal::from_bigint(unscaled.scale_up(-scale as u32)),
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.unscaled.is_zero()
+    }
+
+    // the value when it's a whole number that fits an i64.
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut num = self.clone();
+        num.strip_zeros(0);
+        match num.scale {
+            0 => num.unscaled.to_i64(),
+            _ => None,
+        }
+    }
+
+    // drop the trailing fractional zeros, keeping at least `min_scale` digits.
+    fn strip_zeros(&mut self, min_scale: u32) {
+        let ten = BigInt::from_i64(10);
+        while self.scale > min_scale {
+            let (q, r) = self.unscaled.div_rem(&ten);
+            if !r.is_zero() {
+                break;
+            }
+            self.unscaled = q;
+            self.scale -= 1;
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.unscaled.size()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    fn rescale(&self, scale: u32) -> BigInt {
+        self.unscaled.scale_up(scale - self.scale)
+    }
+
+    // both unscaled values at the larger scale of the two.
+    fn align(&self, oth: &Decimal) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(oth.scale);
+        (self.rescale(scale), oth.rescale(scale), scale)
+    }
+
+    pub fn neg(&self) -> Decimal {
+        Decimal {
+            unscaled: self.unscaled.neg(),
+            scale: self.scale,
+        }
+    }
+
+    pub fn add(&self, oth: &Decimal) -> Decimal {
+        let (a, b, scale) = self.align(oth);
+        Decimal {
+            unscaled: a.add(&b),
+            scale,
+        }
+    }
+
+    pub fn sub(&self, oth: &Decimal) -> Decimal {
+        self.add(&oth.neg())
+    }
+
+    pub fn mul(&self, oth: &Decimal) -> Decimal {
+        Decimal {
+            unscaled: self.unscaled.mul(&oth.unscaled),
+            scale: self.scale + oth.scale,
+        }
+    }
+
+    // the quotient is exact when it fits in DIV_SCALE fractional digits, otherwise it's rounded to
+    // DIV_SCALE digits, half to even. trailing zeros are dropped down to the larger operand scale:
+    // `1.20d / 2` is `0.60`, `1d / 3` is `0.33333333333333333333`. `oth` must not be zero.
+    pub fn div(&self, oth: &Decimal) -> Decimal {
+        let min_scale = self.scale.max(oth.scale);
+        let scale = min_scale.max(DIV_SCALE);
+        // self / oth = (a * 10^-sa) / (b * 10^-sb) = (a * 10^(scale + sb - sa) / b) * 10^-scale
+        let dividend = self.unscaled.scale_up(scale + oth.scale - self.scale);
+        let (mut quotient, rem) = dividend.div_rem(&oth.unscaled);
+
+        // round half to even: compare 2 * |rem| with |divisor|.
+        let twice_rem = rem.add(&rem);
+        let half = cmp_limbs(&twice_rem.limbs, &oth.unscaled.limbs);
+        let odd = quotient.limbs.first().is_some_and(|limb| limb % 2 == 1);
+        if half == Ordering::Greater || (half == Ordering::Equal && odd) {
+            let one = BigInt::from_i64(match dividend.negative != oth.unscaled.negative {
+                true => -1,
+                false => 1,
+            });
+            quotient = quotient.add(&one);
+        }
+
+        let mut res = Decimal {
+            unscaled: quotient,
+            scale,
+        };
+        res.strip_zeros(min_scale);
+        res
+    }
+
+    // truncated toward zero, the result is a whole decimal.
+    pub fn int_div(&self, oth: &Decimal) -> Decimal {
+        let (a, b, _) = self.align(oth);
+        Decimal::from_bigint(a.div_rem(&b).0)
+    }
+
+    // the remainder has the sign of the dividend, like `%` on the other numbers.
+    pub fn rem(&self, oth: &Decimal) -> Decimal {
+        let (a, b, scale) = self.align(oth);
+        Decimal {
+            unscaled: a.div_rem(&b).1,
+            scale,
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, oth: &Self) -> bool {
+        self.cmp(oth) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut num = self.clone();
+        num.strip_zeros(0);
+        num.unscaled.hash(state);
+        num.scale.hash(state);
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, oth: &Self) -> Ordering {
+        let (a, b, _) = self.align(oth);
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, oth: &Self) -> Option<Ordering> {
+        Some(self.cmp(oth))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.unscaled.to_string();
+        let digits = digits.trim_start_matches('-');
+        let scale = self.scale as usize;
+        if self.unscaled.negative {
+            write!(f, "-")?;
+        }
+        if scale == 0 {
+            return write!(f, "{}", digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        write!(f, "{}.{}", int, frac)
+    }
+}
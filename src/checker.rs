@@ -0,0 +1,771 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    ast::{Expr, Param, Pattern, Stmt},
+    error::Error,
+    token::{Token, TokenType},
+};
+
+// `lox --check file` runs this pass after the resolver, the interpreter ignores the annotations.
+// it only knows the types of literals, annotated variables, constants, functions and classes,
+// everything else is `any` and passes: an unannotated program never has a type error.
+pub fn check(stmts: &Vec<Stmt>) -> bool {
+    let mut checker = Checker::new();
+    checker.declare_classes(stmts);
+    checker.check_stmts(stmts);
+    !checker.has_err
+}
+
+#[derive(Clone, PartialEq)]
+enum Type {
+    Any,
+    Num,
+    Str,
+    Bool,
+    Nil,
+    List,
+    Map,
+    Instance(String), // an instance of the class or of one of its subclasses
+    Class(String),    // the class itself, calling it makes an instance
+    Fun(Option<Rc<Signature>>), // `fun` annotations don't know the signature
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Num => write!(f, "num"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::List => write!(f, "list"),
+            Type::Map => write!(f, "map"),
+            Type::Instance(class) => write!(f, "{}", class),
+            Type::Class(_) => write!(f, "class"),
+            Type::Fun(_) => write!(f, "fun"),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct Signature {
+    params: Vec<Type>, // the last one is the type of each extra argument with a rest parameter
+    min: usize,
+    max: Option<usize>, // None with a rest parameter
+    ret: Type,
+}
+
+impl Signature {
+    fn param(&self, i: usize) -> &Type {
+        match self.max {
+            None if i >= self.params.len() => self.params.last().unwrap_or(&Type::Any),
+            _ => self.params.get(i).unwrap_or(&Type::Any),
+        }
+    }
+
+    fn returning(&self, ret: Type) -> Rc<Signature> {
+        Rc::new(Signature {
+            params: self.params.clone(),
+            min: self.min,
+            max: self.max,
+            ret,
+        })
+    }
+
+    fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+
+    // like the arity in the interpreter's error: "2", "1 to 2" or "at least 1".
+    fn arity(&self) -> String {
+        match self.max {
+            Some(max) if max == self.min => max.to_string(),
+            Some(max) => format!("{} to {}", self.min, max),
+            None => format!("at least {}", self.min),
+        }
+    }
+}
+
+struct ClassInfo {
+    super_class: Option<String>,
+    init: Option<Rc<Signature>>, // None until the class is checked, or if its superclass is unknown
+}
+
+struct Checker {
+    // the known types of the variables, a missing one is `any`, and whether the type is an
+    // annotation the variable's assignments must follow.
+    scopes: Vec<HashMap<String, (Type, bool)>>,
+    classes: HashMap<String, ClassInfo>, // the classes the annotations can name.
+    ret: Option<Type>,                   // the return type of the function being checked.
+    has_err: bool,
+}
+
+impl Checker {
+    fn new() -> Self {
+        let native = |params: Vec<Type>, min: usize, ret: Type| {
+            Type::Fun(Some(Rc::new(Signature {
+                max: Some(params.len()),
+                params,
+                min,
+                ret,
+            })))
+        };
+        let globals = HashMap::from([
+            ("clock".to_string(), (native(vec![], 0, Type::Num), false)),
+            (
+                "range".to_string(),
+                (
+                    native(vec![Type::Num, Type::Num, Type::Num], 2, Type::Any),
+                    false,
+                ),
+            ),
+            (
+                "str".to_string(),
+                (native(vec![Type::Any], 1, Type::Str), false),
+            ),
+            (
+                "Fiber".to_string(),
+                (native(vec![Type::Fun(None)], 1, Type::Any), false),
+            ),
+            ("Channel".to_string(), (native(vec![], 0, Type::Any), false)),
+            (
+                "spawn".to_string(),
+                (native(vec![Type::Fun(None)], 1, Type::Any), false),
+            ),
+            ("run".to_string(), (native(vec![], 0, Type::Nil), false)),
+            (
+                "sleep".to_string(),
+                (native(vec![Type::Num], 1, Type::Any), false),
+            ),
+        ]);
+        Checker {
+            scopes: vec![globals],
+            classes: HashMap::new(),
+            ret: None,
+            has_err: false,
+        }
+    }
+
+    fn error(&mut self, token: &Token, msg: &str) {
+        eprintln!("{}", Error::ParseError(token.clone(), msg.to_string()));
+        self.has_err = true;
+    }
+
+    // the class names are known before the checking, an annotation can name a class declared later.
+    fn declare_classes(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Class {
+                    name, super_class, ..
+                } => {
+                    let super_class = match super_class {
+                        Some(Expr::Variable { name }) => Some(name.lexeme.clone()),
+                        _ => None,
+                    };
+                    self.classes.insert(
+                        name.lexeme.clone(),
+                        ClassInfo {
+                            super_class,
+                            init: None,
+                        },
+                    );
+                }
+                Stmt::Function { body, .. } => self.declare_classes(body),
+                Stmt::Block { stmts } => self.declare_classes(stmts),
+                _ => (),
+            }
+        }
+    }
+
+    fn annotation(&mut self, annotation: &Option<Token>) -> Type {
+        let Some(token) = annotation else {
+            return Type::Any;
+        };
+        match token.lexeme.as_str() {
+            "any" => Type::Any,
+            "num" => Type::Num,
+            "str" => Type::Str,
+            "bool" => Type::Bool,
+            "nil" => Type::Nil,
+            "list" => Type::List,
+            "map" => Type::Map,
+            "fun" => Type::Fun(None),
+            name if self.classes.contains_key(name) => Type::Instance(name.to_string()),
+            name => {
+                self.error(token, &format!("Unknown type '{}'.", name));
+                Type::Any
+            }
+        }
+    }
+
+    // whether a value of type `actual` can be stored where `expected` is declared.
+    fn assignable(&self, expected: &Type, actual: &Type) -> bool {
+        match (expected, actual) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Instance(expected), Type::Instance(actual)) => {
+                let mut class = Some(actual);
+                while let Some(name) = class {
+                    if name == expected {
+                        return true;
+                    }
+                    class = self
+                        .classes
+                        .get(name)
+                        .and_then(|info| info.super_class.as_ref());
+                }
+                false
+            }
+            (Type::Fun(_), Type::Fun(_) | Type::Class(_)) => true,
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    fn define(&mut self, name: &Token, t: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), (t, false));
+        }
+    }
+
+    // define a variable with an annotation.
+    fn declare(&mut self, name: &Token, t: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), (t, true));
+        }
+    }
+
+    fn binding(&self, name: &Token) -> (Type, bool) {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .cloned()
+            .unwrap_or((Type::Any, false))
+    }
+
+    fn check_stmts(&mut self, stmts: &Vec<Stmt>) {
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expr } | Stmt::Print { expr, .. } => {
+                self.check_expr(expr);
+            }
+            Stmt::Throw { value, .. } => {
+                self.check_expr(value);
+            }
+            Stmt::Var {
+                name,
+                annotation,
+                initializer,
+                constant,
+                ..
+            } => {
+                let declared = self.annotation(annotation);
+                let t = match initializer {
+                    Some(expr) => self.check_expr(expr),
+                    None => Type::Any,
+                };
+                if !self.assignable(&declared, &t) {
+                    self.error(
+                        name,
+                        &format!(
+                            "Can't assign '{}' to '{}' of type '{}'.",
+                            t, name.lexeme, declared
+                        ),
+                    );
+                }
+                // a constant keeps the type of its value, a variable may be assigned anything later.
+                match (annotation, constant) {
+                    (Some(_), _) => self.declare(name, declared),
+                    (None, true) => self.define(name, t),
+                    (None, false) => self.define(name, Type::Any),
+                }
+            }
+            // the elements and fields are only known when they run.
+            Stmt::Destructure {
+                target,
+                initializer,
+                ..
+            } => {
+                self.check_expr(initializer);
+                for name in target.names() {
+                    self.define(name, Type::Any);
+                }
+            }
+            Stmt::Block { stmts } => {
+                self.scopes.push(HashMap::new());
+                self.check_stmts(stmts);
+                self.scopes.pop();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_expr(condition);
+                self.check_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_stmt(else_branch);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.check_expr(condition);
+                self.check_stmt(body);
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.check_expr(iterable);
+                self.scopes.push(HashMap::new());
+                self.define(name, Type::Any);
+                self.check_stmt(body);
+                self.scopes.pop();
+            }
+            Stmt::Function {
+                name,
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                let signature = self.signature(params, return_type, body);
+                self.define(name, Type::Fun(Some(signature.clone())));
+                self.check_function(params, &signature, body);
+            }
+            Stmt::Return { keyword, value } => {
+                let t = match value {
+                    Some(expr) => self.check_expr(expr),
+                    None => Type::Nil,
+                };
+                if let Some(ret) = self.ret.clone() {
+                    if !self.assignable(&ret, &t) {
+                        self.error(
+                            keyword,
+                            &format!("Can't return '{}' from a function returning '{}'.", t, ret),
+                        );
+                    }
+                }
+            }
+            Stmt::Yield { value, .. } => {
+                if let Some(expr) = value {
+                    self.check_expr(expr);
+                }
+            }
+            Stmt::Class {
+                name,
+                super_class,
+                traits,
+                methods,
+                getters,
+                static_methods,
+                static_fields,
+                ..
+            } => {
+                if let Some(super_class) = super_class {
+                    self.check_expr(super_class);
+                }
+                for t in traits {
+                    self.check_expr(t);
+                }
+                self.define(name, Type::Class(name.lexeme.clone()));
+                let members: Vec<&Stmt> = methods
+                    .iter()
+                    .chain(getters)
+                    .chain(static_methods)
+                    .collect();
+                // the signatures first, the methods can call the class.
+                let signatures: Vec<Rc<Signature>> = members
+                    .iter()
+                    .map(|member| self.method_signature(member))
+                    .collect();
+                let init = methods
+                    .iter()
+                    .zip(&signatures)
+                    .find(|(method, _)| {
+                        matches!(method, Stmt::Function { name, .. } if name.lexeme == "init")
+                    })
+                    .map(|(_, signature)| signature.clone());
+                let init = self.init_signature(name, init);
+                if let Some(info) = self.classes.get_mut(&name.lexeme) {
+                    info.init = init;
+                }
+                self.scopes.push(HashMap::new());
+                for (member, signature) in members.into_iter().zip(&signatures) {
+                    if let Stmt::Function { params, body, .. } = member {
+                        self.check_function(params, signature, body);
+                    }
+                }
+                self.scopes.pop();
+                for (_, value) in static_fields {
+                    self.check_expr(value);
+                }
+            }
+            Stmt::Trait { name, methods, .. } => {
+                self.define(name, Type::Any);
+                for method in methods {
+                    let signature = self.method_signature(method);
+                    if let Stmt::Function { params, body, .. } = method {
+                        self.check_function(params, &signature, body);
+                    }
+                }
+            }
+            Stmt::Import { name, .. } => self.define(name, Type::Any),
+            Stmt::Match { value, cases, .. } => {
+                self.check_expr(value);
+                for case in cases {
+                    self.scopes.push(HashMap::new());
+                    for pattern in &case.patterns {
+                        self.check_pattern(pattern);
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.check_expr(guard);
+                    }
+                    self.check_stmt(&case.body);
+                    self.scopes.pop();
+                }
+            }
+            Stmt::Try {
+                body,
+                catch_clause,
+                finally_body,
+            } => {
+                self.check_stmt(body);
+                if let Some((name, catch_body)) = catch_clause {
+                    self.scopes.push(HashMap::new());
+                    self.define(name, Type::Any);
+                    self.check_stmt(catch_body);
+                    self.scopes.pop();
+                }
+                if let Some(finally_body) = finally_body {
+                    self.check_stmt(finally_body);
+                }
+            }
+        }
+    }
+
+    fn check_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => (),
+            Pattern::Literal { value } => {
+                self.check_expr(value);
+            }
+            Pattern::Binding { name } => self.define(name, Type::Any),
+            Pattern::Instance { class, fields } => {
+                self.check_expr(class);
+                for (_, pattern) in fields {
+                    self.check_pattern(pattern);
+                }
+            }
+            Pattern::List { elements, rest, .. } => {
+                for pattern in elements {
+                    self.check_pattern(pattern);
+                }
+                if let Some(rest) = rest {
+                    self.define(rest, Type::List);
+                }
+            }
+        }
+    }
+
+    fn signature(
+        &mut self,
+        params: &[Param],
+        return_type: &Option<Token>,
+        body: &[Stmt],
+    ) -> Rc<Signature> {
+        let types = params
+            .iter()
+            .map(|param| self.annotation(&param.annotation))
+            .collect();
+        let ret = self.annotation(return_type);
+        Rc::new(Signature {
+            params: types,
+            min: params
+                .iter()
+                .filter(|p| p.default.is_none() && !p.rest)
+                .count(),
+            max: match params.last() {
+                Some(param) if param.rest => None,
+                _ => Some(params.len()),
+            },
+            // calling a generator function returns the generator, whatever it yields.
+            ret: match body.iter().any(Stmt::contains_yield) {
+                true => Type::Any,
+                false => ret,
+            },
+        })
+    }
+
+    fn check_function(&mut self, params: &[Param], signature: &Signature, body: &Vec<Stmt>) {
+        let enclosing = self.ret.take();
+        if body.iter().all(|stmt| !stmt.contains_yield()) {
+            self.ret = Some(signature.ret.clone());
+        }
+        self.scopes.push(HashMap::new());
+        for (i, param) in params.iter().enumerate() {
+            if let Some(default) = &param.default {
+                let t = self.check_expr(default);
+                if !self.assignable(&signature.params[i], &t) {
+                    self.error(
+                        &param.name,
+                        &format!(
+                            "Can't assign '{}' to '{}' of type '{}'.",
+                            t, param.name.lexeme, signature.params[i]
+                        ),
+                    );
+                }
+            }
+            match (param.rest, &param.annotation) {
+                (true, _) => self.define(&param.name, Type::List),
+                (false, Some(_)) => self.declare(&param.name, signature.params[i].clone()),
+                (false, None) => self.define(&param.name, Type::Any),
+            }
+        }
+        self.check_stmts(body);
+        self.scopes.pop();
+        self.ret = enclosing;
+    }
+
+    fn method_signature(&mut self, method: &Stmt) -> Rc<Signature> {
+        let Stmt::Function {
+            name,
+            params,
+            return_type,
+            body,
+            ..
+        } = method
+        else {
+            unreachable!()
+        };
+        let signature = self.signature(params, return_type, body);
+        match name.lexeme.as_str() {
+            // `init` returns the instance, whatever its body returns.
+            "init" => signature.returning(Type::Any),
+            _ => signature,
+        }
+    }
+
+    // the signature of calling the class: its `init`, inherited from the superclass if it has none.
+    fn init_signature(&self, name: &Token, init: Option<Rc<Signature>>) -> Option<Rc<Signature>> {
+        let instance = Type::Instance(name.lexeme.clone());
+        if let Some(init) = init {
+            return Some(init.returning(instance));
+        }
+        match &self.classes.get(&name.lexeme)?.super_class {
+            Some(super_class) => {
+                let init = self.classes.get(super_class)?.init.as_ref()?;
+                Some(init.returning(instance))
+            }
+            None => Some(Rc::new(Signature {
+                params: Vec::new(),
+                min: 0,
+                max: Some(0),
+                ret: instance,
+            })),
+        }
+    }
+
+    // the type of an expression, checking the calls and assignments in it.
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal { value } => match value.t {
+                TokenType::INTEGER { .. }
+                | TokenType::NUMBER { .. }
+                | TokenType::BIGINT { .. }
+                | TokenType::DECIMAL { .. } => Type::Num,
+                TokenType::STRING { .. } => Type::Str,
+                TokenType::TRUE | TokenType::FALSE => Type::Bool,
+                TokenType::NIL => Type::Nil,
+                _ => Type::Any,
+            },
+            Expr::Grouping { expression } => self.check_expr(expression),
+            Expr::Unary { op, right } => {
+                let t = self.check_expr(right);
+                match (&op.t, t) {
+                    (TokenType::BANG, _) => Type::Bool,
+                    (_, Type::Num) => Type::Num,
+                    _ => Type::Any,
+                }
+            }
+            Expr::Binary { left, op, right } => {
+                let (l, r) = (self.check_expr(left), self.check_expr(right));
+                match (&op.t, l, r) {
+                    (
+                        TokenType::GREATER
+                        | TokenType::GreaterEqual
+                        | TokenType::LESS
+                        | TokenType::LessEqual
+                        | TokenType::EqualEqual
+                        | TokenType::BangEqual,
+                        _,
+                        _,
+                    ) => Type::Bool,
+                    (TokenType::PLUS, Type::Str, Type::Str) => Type::Str,
+                    (_, Type::Num, Type::Num) => Type::Num,
+                    _ => Type::Any,
+                }
+            }
+            Expr::Logical { left, right, .. } => {
+                let (l, r) = (self.check_expr(left), self.check_expr(right));
+                if l == r {
+                    l
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.check_expr(condition);
+                let (t, e) = (self.check_expr(then_expr), self.check_expr(else_expr));
+                if t == e {
+                    t
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Variable { name } => self.binding(name).0,
+            Expr::Assign { name, value } => {
+                let t = self.check_expr(value);
+                let (declared, annotated) = self.binding(name);
+                if annotated && !self.assignable(&declared, &t) {
+                    self.error(
+                        name,
+                        &format!(
+                            "Can't assign '{}' to '{}' of type '{}'.",
+                            t, name.lexeme, declared
+                        ),
+                    );
+                }
+                t
+            }
+            Expr::AssignList { targets, value, .. } => {
+                for target in targets {
+                    self.check_expr(target);
+                }
+                self.check_expr(value)
+            }
+            Expr::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                let callee = self.check_expr(callee);
+                let spread = args.iter().any(|arg| matches!(arg, Expr::Spread { .. }));
+                let args: Vec<Type> = args.iter().map(|arg| self.check_expr(arg)).collect();
+                let signature = match callee {
+                    Type::Fun(Some(signature)) => signature,
+                    Type::Class(class) => match self.classes.get(&class) {
+                        Some(ClassInfo {
+                            init: Some(signature),
+                            ..
+                        }) => signature.clone(),
+                        _ => return Type::Instance(class),
+                    },
+                    Type::Num | Type::Str | Type::Bool | Type::Nil | Type::List | Type::Map => {
+                        self.error(paren, "Can only call functions and classes.");
+                        return Type::Any;
+                    }
+                    _ => return Type::Any,
+                };
+                // the number of spread arguments is only known when they run.
+                if !spread {
+                    if !signature.accepts(args.len()) {
+                        self.error(
+                            paren,
+                            &format!(
+                                "Expected {} arguments but got {}.",
+                                signature.arity(),
+                                args.len()
+                            ),
+                        );
+                    }
+                    for (i, arg) in args.iter().enumerate() {
+                        let param = signature.param(i);
+                        if !self.assignable(param, arg) {
+                            self.error(
+                                paren,
+                                &format!(
+                                    "Expected argument {} to be '{}' but got '{}'.",
+                                    i + 1,
+                                    param,
+                                    arg
+                                ),
+                            );
+                        }
+                    }
+                }
+                signature.ret.clone()
+            }
+            Expr::OptionalChain { chain } => {
+                self.check_expr(chain);
+                Type::Any
+            }
+            Expr::Get { object, .. } | Expr::OptionalGet { object, .. } => {
+                self.check_expr(object);
+                Type::Any
+            }
+            Expr::Set { object, value, .. } => {
+                self.check_expr(object);
+                self.check_expr(value)
+            }
+            Expr::Spread { value, .. } => {
+                self.check_expr(value);
+                Type::Any
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.check_expr(element);
+                }
+                Type::List
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.check_expr(key);
+                    self.check_expr(value);
+                }
+                Type::Map
+            }
+            Expr::Index { object, index, .. } => {
+                self.check_expr(object);
+                self.check_expr(index);
+                Type::Any
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.check_expr(object);
+                self.check_expr(index);
+                self.check_expr(value)
+            }
+            Expr::CompoundAssign { target, value, .. } => {
+                self.check_expr(target);
+                self.check_expr(value);
+                Type::Any
+            }
+            Expr::Increment { target, .. } => {
+                self.check_expr(target);
+                Type::Any
+            }
+            // the value the fiber is resumed with.
+            Expr::FiberYield { value, .. } => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+                Type::Any
+            }
+            Expr::Super { .. } | Expr::This { .. } => Type::Any,
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use core::fmt;
 
-use crate::token::{Token, TokenType};
+use crate::{
+    interpreter::Value,
+    token::{Token, TokenType},
+};
 
 fn report(line: u32, pos: &str, msg: &str) {
     eprintln!("[line {}] Error{}: {}", line, pos, msg);
@@ -10,16 +13,37 @@ pub fn scan_error(line: u32, msg: &str) {
     report(line, "", msg);
 }
 
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     ParseError(Token, String),
-    RuntimeError(Token, String),
+    RuntimeError(Token, ErrorKind, String),
+    Throw(Token, Value), // a value thrown by `throw` that isn't caught yet.
+    ShortCircuit,        // a `?.` on nil, the `Expr::OptionalChain` around it makes it nil.
+}
+
+// the kind of a runtime error, a script that catches the error can read it as `e.kind`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Type,       // an operand or callee of the wrong type.
+    Arity,      // a call with the wrong number of arguments.
+    Name,       // an undefined variable.
+    Arithmetic, // integer overflow, division by zero.
+    Import,     // a module that can't be found, compiled, or imports itself.
+    Limit, // the script ran out of fuel, time or memory, or was interrupted, it can't be caught.
+    Runtime, // everything else.
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}Error", self)
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ParseError(token, msg) => match token.t {
-                TokenType::EOF => write!(f, "[line {}] Error{}: {}", token.line, " at end", msg),
+                TokenType::EOF => write!(f, "[line {}] Error at end: {}", token.line, msg),
                 _ => write!(
                     f,
                     "[line {}] Error{}: {}",
@@ -28,7 +52,11 @@ impl fmt::Display for Error {
                     msg
                 ),
             },
-            Error::RuntimeError(token, msg) => write!(f, "{}\n[line {}]", msg, token.line),
+            Error::RuntimeError(token, _, msg) => write!(f, "{}\n[line {}]", msg, token.line),
+            // a caught runtime error that is thrown again keeps its message and line.
+            Error::Throw(_, Value::Error(e)) => write!(f, "{}\n[line {}]", e.message, e.line),
+            Error::Throw(token, value) => write!(f, "{}\n[line {}]", value, token.line),
+            Error::ShortCircuit => write!(f, "Short circuit outside of an optional chain."),
         }
     }
 }
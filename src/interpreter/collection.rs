@@ -0,0 +1,222 @@
+use std::{cell::RefCell, collections::HashMap, fmt, mem, rc::Rc};
+
+use super::{
+    native::NativeFn,
+    value::{type_error, Arity, OpError},
+    Interpreter, Value,
+};
+use crate::{bignum::Decimal, error::ErrorKind};
+
+// the values a map can be keyed by, numbers that are equal are the same key: `m[1]`, `m[1.0]`,
+// `m[1n]` and `m[1.00d]`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Nil,
+    Bool(bool),
+    Integer(i64),   // any whole number that fits
+    Exact(Decimal), // any other finite number, a float by its exact value
+    Float(u64),     // the bits of an infinite or NaN float
+    String(String),
+}
+
+impl MapKey {
+    fn from(value: &Value) -> Result<MapKey, OpError> {
+        match value {
+            Value::Nil => Ok(MapKey::Nil),
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            Value::Integer(n) => Ok(MapKey::Integer(*n)),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                Ok(MapKey::Integer(*n as i64))
+            }
+            Value::Number(n) if n.is_finite() => Ok(MapKey::exact(Decimal::from_f64(*n))),
+            Value::Number(n) => Ok(MapKey::Float(n.to_bits())),
+            Value::BigInt(n) => Ok(MapKey::exact(Decimal::from_bigint(n.clone()))),
+            Value::Decimal(n) => Ok(MapKey::exact(n.clone())),
+            Value::String(s) => Ok(MapKey::String(s.clone())),
+            _ => Err(type_error(
+                "Map keys must be nil, booleans, numbers or strings.",
+            )),
+        }
+    }
+
+    fn exact(n: Decimal) -> MapKey {
+        match n.to_i64() {
+            Some(n) => MapKey::Integer(n),
+            None => MapKey::Exact(n),
+        }
+    }
+}
+
+// a map keeps its entries in insertion order, it's the order `for-in` walks the keys in.
+#[derive(Default)]
+pub struct MapValue {
+    entries: Vec<(Value, Value)>,
+    index: HashMap<MapKey, usize>,
+}
+
+impl MapValue {
+    pub fn get(&self, key: &Value) -> Result<Option<Value>, OpError> {
+        let key = MapKey::from(key)?;
+        Ok(self.index.get(&key).map(|&i| self.entries[i].1.clone()))
+    }
+
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<(), OpError> {
+        match self.index.get(&MapKey::from(&key)?) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(MapKey::from(&key)?, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        self.entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+// `range(start, end, step)`: the integers from start up to, not including, end.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64, // negative to count down
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "range({}, {}, {})", self.start, self.end, self.step)
+    }
+}
+
+thread_local! {
+    // the lists and maps being printed, outermost first.
+    static PRINTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+// lists and maps nested deeper than this print as `[...]` too, the printing recurses on the Rust stack.
+const MAX_NESTING: usize = 256;
+
+// print a list or map with `print_items`, `None` when it is already being printed:
+// a list containing itself would print forever, it prints as `[...]` inside itself instead.
+pub fn printing<T>(container: *const (), print_items: impl FnOnce() -> T) -> Option<T> {
+    let nested = PRINTING.with_borrow_mut(|printing| {
+        if printing.contains(&container) || printing.len() >= MAX_NESTING {
+            return false;
+        }
+        printing.push(container);
+        true
+    });
+    if !nested {
+        return None;
+    }
+    let printed = print_items();
+    PRINTING.with_borrow_mut(|printing| printing.pop());
+    Some(printed)
+}
+
+// how a value looks inside a printed list or map: strings are quoted.
+pub fn repr(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        _ => value.to_string(),
+    }
+}
+
+// the position of an index into a list or string of the length.
+fn position(len: usize, index: &Value) -> Result<usize, OpError> {
+    match index {
+        Value::Integer(i) if *i >= 0 && (*i as usize) < len => Ok(*i as usize),
+        Value::Integer(_) => Err((ErrorKind::Runtime, "Index out of range.".to_string())),
+        _ => Err(type_error("Index must be an integer.")),
+    }
+}
+
+// `object[index]`
+pub fn get_index(object: &Value, index: &Value) -> Result<Value, OpError> {
+    match object {
+        Value::List(list) => {
+            let list = list.borrow();
+            Ok(list[position(list.len(), index)?].clone())
+        }
+        Value::Map(map) => match map.borrow().get(index)? {
+            Some(value) => Ok(value),
+            None => Err((ErrorKind::Name, format!("Undefined key {}.", repr(index)))),
+        },
+        Value::String(s) => {
+            let c = s.chars().nth(position(s.chars().count(), index)?).unwrap();
+            Ok(Value::String(c.to_string()))
+        }
+        _ => Err(type_error("Only lists, maps and strings can be indexed.")),
+    }
+}
+
+// `object[index] = value`
+pub fn set_index(object: &Value, index: &Value, value: Value) -> Result<(), OpError> {
+    match object {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let i = position(list.len(), index)?;
+            list[i] = value;
+            Ok(())
+        }
+        Value::Map(map) => map.borrow_mut().insert(index.clone(), value),
+        _ => Err(type_error("Only lists and maps support index assignment.")),
+    }
+}
+
+// the properties of the built-in values: `length` and their methods.
+pub fn get_property(object: &Value, name: &str) -> Option<Value> {
+    let method = |name: &'static str, arity: usize, func| {
+        Some(Value::Callable(Rc::new(NativeFn::method(
+            name,
+            Arity::exact(arity),
+            object.clone(),
+            func,
+        ))))
+    };
+    match (object, name) {
+        (Value::List(list), "length") => Some(Value::Integer(list.borrow().len() as i64)),
+        (Value::List(_), "push") => method("push", 1, list_push),
+        (Value::Map(map), "length") => Some(Value::Integer(map.borrow().len() as i64)),
+        (Value::Map(_), "keys") => method("keys", 0, map_keys),
+        (Value::Map(_), "has") => method("has", 1, map_has),
+        (Value::String(s), "length") => Some(Value::Integer(s.chars().count() as i64)),
+        _ => None,
+    }
+}
+
+fn list_push(i: &Interpreter, this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    if let Value::List(list) = this {
+        i.reserve(args.len() * mem::size_of::<Value>())?;
+        list.borrow_mut().extend(args);
+    }
+    Ok(Value::Nil)
+}
+
+fn map_keys(i: &Interpreter, this: &Value, _args: Vec<Value>) -> Result<Value, OpError> {
+    match this {
+        Value::Map(map) => {
+            let keys = map.borrow().keys();
+            i.reserve(keys.len() * mem::size_of::<Value>())?;
+            Ok(Value::List(Rc::new(RefCell::new(keys))))
+        }
+        _ => Ok(Value::Nil),
+    }
+}
+
+fn map_has(_i: &Interpreter, this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    match this {
+        Value::Map(map) => Ok(Value::Bool(map.borrow().get(&args[0])?.is_some())),
+        _ => Ok(Value::Nil),
+    }
+}
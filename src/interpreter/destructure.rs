@@ -0,0 +1,105 @@
+use std::{cell::RefCell, mem, rc::Rc};
+
+use super::{Interpreter, Value};
+use crate::{
+    ast::{Destructure, Expr},
+    error::{Error, ErrorKind},
+    token::Token,
+};
+
+impl Interpreter {
+    // the values a destructuring declaration binds to its names.
+    pub(super) fn destructure<'a>(
+        &mut self,
+        target: &'a Destructure,
+        value: &Value,
+    ) -> Result<Vec<(&'a Token, Value)>, Error> {
+        match target {
+            Destructure::List {
+                bracket,
+                names,
+                rest,
+            } => {
+                let mut values = self.elements(bracket, value, names.len(), rest.is_some())?;
+                let rest_values = values.split_off(names.len());
+                let mut bindings: Vec<(&Token, Value)> = names.iter().zip(values).collect();
+                if let Some(rest) = rest {
+                    self.allocate(bracket, rest_values.len() * mem::size_of::<Value>())?;
+                    bindings.push((rest, Value::List(Rc::new(RefCell::new(rest_values)))));
+                }
+                Ok(bindings)
+            }
+            Destructure::Fields { brace, names } => {
+                let mut bindings = Vec::new();
+                for name in names {
+                    let field = match value {
+                        Value::Instance(_) => self.get_property(value, name)?,
+                        Value::Map(_) => {
+                            self.get_index(name, value, &Value::String(name.lexeme.clone()))?
+                        }
+                        _ => {
+                            return Err(Error::RuntimeError(
+                                brace.clone(),
+                                ErrorKind::Type,
+                                "Only instances and maps can be destructured by name.".to_string(),
+                            ))
+                        }
+                    };
+                    bindings.push((name, field));
+                }
+                Ok(bindings)
+            }
+        }
+    }
+
+    // the elements of a list destructured into `count` names, or at least `count` with a rest.
+    pub(super) fn elements(
+        &mut self,
+        bracket: &Token,
+        value: &Value,
+        count: usize,
+        rest: bool,
+    ) -> Result<Vec<Value>, Error> {
+        let Value::List(list) = value else {
+            return Err(Error::RuntimeError(
+                bracket.clone(),
+                ErrorKind::Type,
+                "Only lists can be destructured by position.".to_string(),
+            ));
+        };
+        let values = list.borrow().clone();
+        if values.len() < count || (!rest && values.len() > count) {
+            let expected = match rest {
+                true => format!("at least {}", count),
+                false => count.to_string(),
+            };
+            return Err(Error::RuntimeError(
+                bracket.clone(),
+                ErrorKind::Runtime,
+                format!("Expected {} elements but got {}.", expected, values.len()),
+            ));
+        }
+        Ok(values)
+    }
+
+    // `target = value` for a target of `[a, b.c, d[0]] = list`.
+    pub(super) fn assign_target(&mut self, target: &Expr, value: Value) -> Result<(), Error> {
+        match target {
+            Expr::Variable { name } => self.assign_variable(name, target, value),
+            Expr::Get { object, name } => {
+                let object = self.eval(object)?;
+                Self::set_property(&object, name, value)
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.eval(object)?;
+                let index = self.eval(index)?;
+                self.set_index(bracket, &object, &index, value)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
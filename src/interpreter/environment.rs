@@ -1,10 +1,19 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
-use crate::{error::Error, interpreter::Value, token::Token};
+use crate::{
+    error::{Error, ErrorKind},
+    interpreter::Value,
+    token::Token,
+};
 
 #[derive(Clone)]
 pub struct Environment {
     cur: HashMap<String, Value>,
+    constants: HashSet<String>, // the names declared with `const`.
     outer_layer: Option<Rc<RefCell<Environment>>>,
 }
 
@@ -12,6 +21,7 @@ impl Environment {
     pub fn new() -> Self {
         Environment {
             cur: HashMap::new(),
+            constants: HashSet::new(),
             outer_layer: None,
         }
     }
@@ -19,6 +29,7 @@ impl Environment {
     pub fn from(outer: &Rc<RefCell<Environment>>) -> Self {
         Self {
             cur: HashMap::new(),
+            constants: HashSet::new(),
             outer_layer: Some(Rc::clone(outer)),
         }
     }
@@ -28,6 +39,17 @@ impl Environment {
         self.cur.insert(name, value);
     }
 
+    pub fn define_const(&mut self, name: String, value: Value) {
+        self.constants.insert(name.clone());
+        self.cur.insert(name, value);
+    }
+
+    // the resolver rejects a local constant being assigned or declared again,
+    // but globals are only checked here, at runtime.
+    pub fn is_const(&self, name: &str) -> bool {
+        self.constants.contains(name)
+    }
+
     // 处理定义但是没有初始化的变量：
     // Lox 是一门动态类型语言，为了简单，会把变量的值设置为 nil.
     // 所以调用`get`会得到 nil.
@@ -48,18 +70,18 @@ impl Environment {
 
         Err(Error::RuntimeError(
             name.clone(),
+            ErrorKind::Name,
             format!("Undefined variable '{}'.", &name.lexeme),
         ))
     }
 
     fn ancestor(&self, distance: usize) -> Option<Rc<RefCell<Environment>>> {
-        let mut anc = None;
+        let mut anc = self.outer_layer.clone()?;
         for _ in 1..distance {
-            if let Some(outer) = &self.outer_layer {
-                anc = Some(outer.clone());
-            }
+            let outer = anc.borrow().outer_layer.clone()?;
+            anc = outer;
         }
-        anc
+        Some(anc)
     }
 
     pub fn get_at(&self, distance: usize, name: &String) -> Result<Value, Error> {
@@ -73,8 +95,15 @@ impl Environment {
     }
 
     pub fn assign(&mut self, name: Token, value: Value) -> Result<(), Error> {
-        if self.cur.contains_key(&name.lexeme) {
-            self.cur.insert(name.lexeme, value);
+        if let Some(slot) = self.cur.get_mut(&name.lexeme) {
+            if self.constants.contains(&name.lexeme) {
+                return Err(Error::RuntimeError(
+                    name.clone(),
+                    ErrorKind::Runtime,
+                    format!("Can't assign to constant '{}'.", &name.lexeme),
+                ));
+            }
+            *slot = value;
             return Ok(());
         }
 
@@ -84,6 +113,7 @@ impl Environment {
 
         Err(Error::RuntimeError(
             name.clone(),
+            ErrorKind::Name,
             format!("Undefined variable '{}'.", &name.lexeme),
         ))
     }
@@ -0,0 +1,417 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, VecDeque},
+    mem, panic,
+    rc::Rc,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use super::{
+    native::NativeFn,
+    value::{type_error, Arity, Callable, FuncValue, OpError},
+    Interpreter, Value,
+};
+use crate::{
+    error::{Error, ErrorKind},
+    token::Token,
+};
+
+// a function that runs a bit at a time: `resume` runs it up to its next `Fiber.yield(value)` and
+// returns the value, the `Fiber.yield` evaluates to what the next `resume` is given.
+// the interpreter recurses on the Rust stack, so a fiber has a thread, and a stack, of its own:
+// it can yield from any call its function makes. only one thread runs at a time, the one resuming
+// a fiber waits until the fiber yields or returns.
+pub struct Fiber {
+    func: Rc<FuncValue>,
+    scheduled: bool, // `spawn` made it, only the scheduler resumes it.
+    state: RefCell<State>,
+}
+
+enum State {
+    New,
+    Suspended(Thread),
+    Running,
+    Done,
+}
+
+// the thread of a fiber that has started.
+struct Thread {
+    resume: Sender<Handoff<Value>>,
+    events: Receiver<Handoff<Event>>,
+    handle: JoinHandle<()>,
+}
+
+// what the thread of a fiber hands back to the one resuming it.
+enum Event {
+    Yield(Value),
+    Return(Result<Value, Error>),
+}
+
+// the fiber's end of its thread, the interpreter running the fiber's calls has it.
+pub(super) struct Yielder {
+    resume: Receiver<Handoff<Value>>,
+    events: Sender<Handoff<Event>>,
+}
+
+// a value going to the thread that runs next. values are `Rc`s, which aren't `Send`: it's sound
+// because the thread handing a value over blocks until it gets one back, so the `Rc`s are never
+// touched by two threads at a time, and the channel orders what one did before the other goes on.
+struct Handoff<T>(T);
+
+unsafe impl<T> Send for Handoff<T> {}
+
+impl<T> Handoff<T> {
+    // a closure moves the whole `Handoff` this way, not just the value in it.
+    fn take(self) -> T {
+        self.0
+    }
+}
+
+// what the thread of a fiber nobody can resume any more unwinds with.
+struct Cancelled;
+
+impl Fiber {
+    pub fn name(&self) -> &str {
+        self.func.name()
+    }
+}
+
+// a suspended fiber that's dropped stops waiting: its thread unwinds, and this one waits for it
+// to finish, so it doesn't drop the `Rc`s on its stack while another thread runs.
+impl Drop for Fiber {
+    fn drop(&mut self) {
+        if let State::Suspended(thread) = mem::replace(self.state.get_mut(), State::Done) {
+            drop(thread.resume);
+            let _ = thread.handle.join();
+        }
+    }
+}
+
+// values passed between fibers: a scheduled fiber receives the next one with `Fiber.yield(channel)`.
+#[derive(Default)]
+pub struct Channel {
+    values: RefCell<VecDeque<Value>>,        // sent, not received yet.
+    receivers: RefCell<VecDeque<Rc<Fiber>>>, // the fibers waiting for a value, first come first served.
+}
+
+impl Channel {
+    // a waiting fiber is ready to run with the value, else the channel keeps it.
+    fn send(&self, scheduler: &Scheduler, value: Value) {
+        match self.receivers.borrow_mut().pop_front() {
+            Some(fiber) => scheduler.ready.borrow_mut().push_back((fiber, Some(value))),
+            None => self.values.borrow_mut().push_back(value),
+        }
+    }
+}
+
+// runs the fibers `spawn` made, one at a time in the order they get ready, so a script always
+// runs the same way. the clock is virtual: `sleep` doesn't wait, when no fiber is ready the
+// clock jumps to the next timer.
+#[derive(Default)]
+pub(super) struct Scheduler {
+    ready: RefCell<VecDeque<(Rc<Fiber>, Option<Value>)>>, // and what to resume each with.
+    timers: RefCell<BTreeMap<(i64, u64), Rc<Channel>>>, // by time, then in the order they were set.
+    timers_set: Cell<u64>,
+    now: Cell<i64>, // in milliseconds
+    running: Cell<bool>,
+}
+
+// the function of `Fiber(fn)` and `spawn(fn)`.
+fn fiber_function(value: &Value) -> Result<Rc<FuncValue>, OpError> {
+    match value {
+        Value::Callable(c) => c.clone().as_function(),
+        _ => None,
+    }
+    .ok_or_else(|| type_error("A fiber can only run a function."))
+}
+
+// Fiber(fn)
+pub fn new_fiber(i: &Interpreter, _this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    let func = fiber_function(&args[0])?;
+    i.reserve(mem::size_of::<Fiber>())?;
+    Ok(Value::Fiber(Rc::new(Fiber {
+        func,
+        scheduled: false,
+        state: RefCell::new(State::New),
+    })))
+}
+
+// spawn(fn): a fiber the scheduler starts at the next `run()`.
+pub fn spawn(i: &Interpreter, _this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    let func = fiber_function(&args[0])?;
+    i.reserve(mem::size_of::<Fiber>())?;
+    let fiber = Rc::new(Fiber {
+        func,
+        scheduled: true,
+        state: RefCell::new(State::New),
+    });
+    i.scheduler
+        .ready
+        .borrow_mut()
+        .push_back((fiber.clone(), None));
+    Ok(Value::Fiber(fiber))
+}
+
+// sleep(ms): a channel that receives the time `ms` milliseconds from now.
+pub fn sleep(i: &Interpreter, _this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    let ms = match args[0] {
+        Value::Integer(ms) if ms >= 0 => ms,
+        _ => return Err(type_error("Sleep time must be a non-negative integer.")),
+    };
+    i.reserve(mem::size_of::<Channel>())?;
+    let scheduler = &i.scheduler;
+    let channel = Rc::new(Channel::default());
+    let time = scheduler.now.get().saturating_add(ms);
+    let order = scheduler.timers_set.get();
+    scheduler.timers_set.set(order + 1);
+    scheduler
+        .timers
+        .borrow_mut()
+        .insert((time, order), channel.clone());
+    Ok(Value::Channel(channel))
+}
+
+// Channel()
+pub fn new_channel(i: &Interpreter, _this: &Value, _args: Vec<Value>) -> Result<Value, OpError> {
+    i.reserve(mem::size_of::<Channel>())?;
+    Ok(Value::Channel(Rc::new(Channel::default())))
+}
+
+fn channel_send(i: &Interpreter, this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    if let Value::Channel(channel) = this {
+        i.reserve(mem::size_of::<Value>())?;
+        let value = args.into_iter().next().unwrap_or(Value::Nil);
+        channel.send(&i.scheduler, value);
+    }
+    Ok(Value::Nil)
+}
+
+// the properties of fibers and channels.
+pub fn get_property(object: &Value, name: &str) -> Option<Value> {
+    match (object, name) {
+        (Value::Fiber(fiber), "resume") => Some(Value::Callable(Rc::new(Resume {
+            fiber: fiber.clone(),
+        }))),
+        (Value::Fiber(fiber), "isDone") => {
+            Some(Value::Bool(matches!(*fiber.state.borrow(), State::Done)))
+        }
+        (Value::Channel(_), "send") => Some(Value::Callable(Rc::new(NativeFn::method(
+            "send",
+            Arity::exact(1),
+            object.clone(),
+            channel_send,
+        )))),
+        (Value::Channel(channel), "length") => {
+            Some(Value::Integer(channel.values.borrow().len() as i64))
+        }
+        _ => None,
+    }
+}
+
+// `fiber.resume(value)`, it runs the fiber so it needs the interpreter.
+struct Resume {
+    fiber: Rc<Fiber>,
+}
+
+impl Callable for Resume {
+    fn call(
+        self: Rc<Self>,
+        mut i: Interpreter,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        if self.fiber.scheduled {
+            return Err(runtime_error(
+                paren,
+                "Can't resume a fiber the scheduler runs.",
+            ));
+        }
+        // a fiber returning gives its return value, like a yield.
+        let (value, _) = i.resume_fiber(paren, &self.fiber, args.into_iter().next())?;
+        Ok(value)
+    }
+    fn arity(&self) -> Arity {
+        Arity::range(0, 1)
+    }
+    fn to_string(&self) -> String {
+        String::from("<native fn resume>")
+    }
+}
+
+// run(): run the spawned fibers until none is ready and no timer is left.
+// a fiber still waiting on a channel then stays suspended, a later `run()` may resume it.
+pub struct Run;
+
+impl Callable for Run {
+    fn call(
+        self: Rc<Self>,
+        mut i: Interpreter,
+        paren: &Token,
+        _args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let scheduler = i.scheduler.clone();
+        if scheduler.running.replace(true) {
+            return Err(runtime_error(paren, "The scheduler is already running."));
+        }
+        let res = i.run_scheduler(paren, &scheduler);
+        scheduler.running.set(false);
+        res.map(|_| Value::Nil)
+    }
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
+    }
+    fn to_string(&self) -> String {
+        String::from("<native fn run>")
+    }
+}
+
+fn runtime_error(token: &Token, msg: &str) -> Error {
+    Error::RuntimeError(token.clone(), ErrorKind::Runtime, msg.to_string())
+}
+
+impl Interpreter {
+    // run the fiber up to its next `Fiber.yield` or to its end, `sent` is what the `Fiber.yield`
+    // it's suspended at evaluates to, or the argument of its function when it hasn't started.
+    // the bool is whether the fiber returned.
+    fn resume_fiber(
+        &mut self,
+        token: &Token,
+        fiber: &Fiber,
+        sent: Option<Value>,
+    ) -> Result<(Value, bool), Error> {
+        match &*fiber.state.borrow() {
+            State::Running => return Err(runtime_error(token, "Fiber is already running.")),
+            State::Done => return Err(runtime_error(token, "Can't resume a finished fiber.")),
+            State::New => Interpreter::check_arity(
+                &(fiber.func.clone() as Rc<dyn Callable>),
+                token,
+                sent.iter().count(),
+            )?,
+            State::Suspended(_) => (),
+        }
+        // from when the fiber's thread runs to when it hands something back, this one touches
+        // nothing the fiber may use.
+        let state = mem::replace(&mut *fiber.state.borrow_mut(), State::Running);
+        let thread = match state {
+            State::New => match self.start_fiber(token, fiber, sent) {
+                Ok(thread) => thread,
+                Err(e) => {
+                    *fiber.state.borrow_mut() = State::New;
+                    return Err(e);
+                }
+            },
+            State::Suspended(thread) => {
+                let _ = thread.resume.send(Handoff(sent.unwrap_or(Value::Nil)));
+                thread
+            }
+            State::Running | State::Done => unreachable!(),
+        };
+        let event = thread.events.recv();
+        let mut state = fiber.state.borrow_mut();
+        match event.map(Handoff::take) {
+            Ok(Event::Yield(value)) => {
+                *state = State::Suspended(thread);
+                Ok((value, false))
+            }
+            Ok(Event::Return(res)) => {
+                *state = State::Done;
+                let _ = thread.handle.join();
+                res.map(|value| (value, true))
+            }
+            // the fiber's thread panicked, the panic goes on in this one.
+            Err(_) => {
+                *state = State::Done;
+                match thread.handle.join() {
+                    Err(payload) => panic::resume_unwind(payload),
+                    Ok(()) => unreachable!(),
+                }
+            }
+        }
+    }
+
+    // a thread calling the fiber's function, with a stack as big as the main one.
+    fn start_fiber(
+        &self,
+        token: &Token,
+        fiber: &Fiber,
+        arg: Option<Value>,
+    ) -> Result<Thread, Error> {
+        let (resume, resumed) = mpsc::channel();
+        let (events, received) = mpsc::channel();
+        let mut i = self.clone();
+        i.depth = 0;
+        i.tail_call = None;
+        i.fiber = Some(Rc::new(Yielder {
+            resume: resumed,
+            events: events.clone(),
+        }));
+        let callee: Rc<dyn Callable> = fiber.func.clone();
+        let start = Handoff((i, callee, token.clone(), arg.into_iter().collect()));
+        let handle = thread::Builder::new()
+            .stack_size(self.limits.stack_size)
+            .spawn(move || {
+                let res = Interpreter::run_fiber(start.take());
+                let _ = events.send(Handoff(Event::Return(res)));
+            })
+            .map_err(|e| runtime_error(token, &format!("Failed to start the fiber: {}.", e)))?;
+        Ok(Thread {
+            resume,
+            events: received,
+            handle,
+        })
+    }
+
+    // the body of a fiber's thread. what it returns is all that's left of the call when it's
+    // handed back, the rest is dropped here, before the resumer goes on.
+    fn run_fiber(
+        (mut i, callee, paren, args): (Interpreter, Rc<dyn Callable>, Token, Vec<Value>),
+    ) -> Result<Value, Error> {
+        i.enter_thread();
+        i.call(callee, &paren, args)
+    }
+
+    // `Fiber.yield(value)`: hand the value to the fiber's resumer and wait for the next `resume`.
+    pub(super) fn fiber_yield(&self, keyword: &Token, value: Value) -> Result<Value, Error> {
+        let Some(fiber) = &self.fiber else {
+            return Err(runtime_error(keyword, "Can only yield inside a fiber."));
+        };
+        let _ = fiber.events.send(Handoff(Event::Yield(value)));
+        match fiber.resume.recv() {
+            Ok(value) => Ok(value.take()),
+            // the fiber was dropped, its thread unwinds without running any more Lox code.
+            Err(_) => panic::resume_unwind(Box::new(Cancelled)),
+        }
+    }
+
+    fn run_scheduler(&mut self, token: &Token, scheduler: &Scheduler) -> Result<(), Error> {
+        loop {
+            let next = scheduler.ready.borrow_mut().pop_front();
+            let Some((fiber, sent)) = next else {
+                // nothing to run before the next timer, the clock jumps to it.
+                let Some(((time, _), channel)) = scheduler.timers.borrow_mut().pop_first() else {
+                    return Ok(());
+                };
+                scheduler.now.set(time);
+                channel.send(scheduler, Value::Integer(time));
+                continue;
+            };
+            match self.resume_fiber(token, &fiber, sent)? {
+                (_, true) => (),
+                // a fiber yielding a channel waits for its next value.
+                (Value::Channel(channel), false) => {
+                    let value = channel.values.borrow_mut().pop_front();
+                    match value {
+                        Some(value) => scheduler.ready.borrow_mut().push_back((fiber, Some(value))),
+                        None => channel.receivers.borrow_mut().push_back(fiber),
+                    }
+                }
+                // any other value lets the other ready fibers run first.
+                (_, false) => scheduler
+                    .ready
+                    .borrow_mut()
+                    .push_back((fiber, Some(Value::Nil))),
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::{cell::RefCell, mem, rc::Rc};
+
+use super::{
+    environment::Environment,
+    iterator::Iter,
+    module::Module,
+    value::{Arity, Callable},
+    Interpreter, Value,
+};
+use crate::{
+    ast::{Expr, Stmt},
+    error::{Error, ErrorKind},
+    token::Token,
+};
+
+type Env = Rc<RefCell<Environment>>;
+
+// `execute` can't stop half way through a statement and continue later, so a generator runs
+// its body with a stack of frames, innermost last, that it keeps between two `next()` calls.
+// only the statements with a `yield` in them are frames, the others run through `execute`.
+// so a generator can only suspend in its own body, never in a function it calls: the calls are
+// still on the Rust stack. a fiber, which yields from any depth, runs on a thread of its own.
+enum Frame {
+    // the statements of the body or a block, `next` is the first one not run yet.
+    Stmts {
+        stmts: Rc<Vec<Stmt>>,
+        next: usize,
+    },
+    // the env to go back to at the end of a block, a loop iteration or a case.
+    Scope(Env),
+    While {
+        condition: Rc<Expr>,
+        body: Rc<Stmt>,
+    },
+    ForIn {
+        name: String,
+        keyword: Token,
+        iter: Iter,
+        body: Rc<Stmt>,
+    },
+}
+
+// what running a statement did to the generator.
+enum Step {
+    Continue,
+    Yield(Value),
+    Return,
+}
+
+// the object calling a generator function returns: each `next()` runs its body up to the next `yield`.
+pub struct Generator {
+    name: String,
+    state: RefCell<State>,
+}
+
+struct State {
+    frames: Vec<Frame>, // empty once the body has returned or failed.
+    env: Env,           // the env the body stopped in.
+    module: Rc<Module>,
+    peeked: Option<Option<Value>>, // `hasNext()` runs the body ahead, the next `next()` returns this.
+    running: bool,
+}
+
+impl Generator {
+    // `env` holds the parameters of the call, the body hasn't run yet.
+    pub fn new(name: String, body: Rc<Vec<Stmt>>, env: Env, module: Rc<Module>) -> Generator {
+        Generator {
+            name,
+            state: RefCell::new(State {
+                frames: vec![Frame::Stmts {
+                    stmts: body,
+                    next: 0,
+                }],
+                env,
+                module,
+                peeked: None,
+                running: false,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// the properties of a generator: `next()` and `hasNext()`.
+pub fn get_property(generator: &Rc<Generator>, name: &str) -> Option<Value> {
+    let name = match name {
+        "next" => "next",
+        "hasNext" => "hasNext",
+        _ => return None,
+    };
+    Some(Value::Callable(Rc::new(GeneratorMethod {
+        generator: generator.clone(),
+        name,
+    })))
+}
+
+// `generator.next` and `generator.hasNext`, they run the body so they need the interpreter.
+struct GeneratorMethod {
+    generator: Rc<Generator>,
+    name: &'static str,
+}
+
+impl Callable for GeneratorMethod {
+    fn call(
+        self: Rc<Self>,
+        mut i: Interpreter,
+        paren: &Token,
+        _args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        match self.name {
+            // an exhausted generator returns nil.
+            "next" => Ok(i.resume(paren, &self.generator)?.unwrap_or(Value::Nil)),
+            _ => i.has_next(paren, &self.generator).map(Value::Bool),
+        }
+    }
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
+    }
+    fn to_string(&self) -> String {
+        format!("<native fn {}>", self.name)
+    }
+}
+
+impl Interpreter {
+    // run the generator to its next `yield`, `None` when its body is done.
+    pub(super) fn resume(
+        &mut self,
+        token: &Token,
+        generator: &Generator,
+    ) -> Result<Option<Value>, Error> {
+        let mut frames = {
+            let mut state = generator.state.borrow_mut();
+            if let Some(value) = state.peeked.take() {
+                return Ok(value);
+            }
+            if state.running {
+                return Err(Error::RuntimeError(
+                    token.clone(),
+                    ErrorKind::Runtime,
+                    "Generator is already running.".to_string(),
+                ));
+            }
+            state.running = true;
+            mem::take(&mut state.frames)
+        };
+
+        let (previous_env, previous_module) = (self.env.clone(), self.module.clone());
+        {
+            let state = generator.state.borrow();
+            self.env = state.env.clone();
+            self.module = state.module.clone();
+        }
+        let res = self.run_frames(&mut frames);
+        let mut state = generator.state.borrow_mut();
+        state.running = false;
+        // after a return or an error the frames are dropped, the generator is done.
+        if let Ok(Some(_)) = res {
+            state.frames = frames;
+            state.env = self.env.clone();
+        }
+        self.env = previous_env;
+        self.module = previous_module;
+        res
+    }
+
+    pub(super) fn has_next(&mut self, token: &Token, generator: &Generator) -> Result<bool, Error> {
+        let value = self.resume(token, generator)?;
+        let has_next = value.is_some();
+        generator.state.borrow_mut().peeked = Some(value);
+        Ok(has_next)
+    }
+
+    fn run_frames(&mut self, frames: &mut Vec<Frame>) -> Result<Option<Value>, Error> {
+        while let Some(frame) = frames.last_mut() {
+            let step = match frame {
+                Frame::Stmts { stmts, next } => {
+                    if *next == stmts.len() {
+                        frames.pop();
+                        continue;
+                    }
+                    let (stmts, index) = (stmts.clone(), *next);
+                    *next += 1;
+                    self.step(frames, &stmts[index])?
+                }
+                Frame::Scope(env) => {
+                    self.env = env.clone();
+                    frames.pop();
+                    continue;
+                }
+                Frame::While { condition, body } => {
+                    let (condition, body) = (condition.clone(), body.clone());
+                    if !self.eval(&condition)?.is_truthy() {
+                        frames.pop();
+                        continue;
+                    }
+                    self.step(frames, &body)?
+                }
+                Frame::ForIn {
+                    name,
+                    keyword,
+                    iter,
+                    body,
+                } => {
+                    let Some(value) = self.next(keyword, iter)? else {
+                        frames.pop();
+                        continue;
+                    };
+                    let (name, body) = (name.clone(), body.clone());
+                    // a fresh env every iteration, like `execute` does.
+                    frames.push(Frame::Scope(self.env.clone()));
+                    self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                    self.env.borrow_mut().define(name, value);
+                    self.step(frames, &body)?
+                }
+            };
+            match step {
+                Step::Continue => (),
+                Step::Yield(value) => return Ok(Some(value)),
+                Step::Return => {
+                    frames.clear();
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // run a statement of the generator's body, pushing a frame for it if it can yield.
+    fn step(&mut self, frames: &mut Vec<Frame>, stmt: &Stmt) -> Result<Step, Error> {
+        if !stmt.contains_yield() {
+            return match self.execute(stmt)? {
+                (_, true) => Ok(Step::Return),
+                (_, false) => Ok(Step::Continue),
+            };
+        }
+        match stmt {
+            Stmt::Yield { value, .. } => {
+                let value = match value {
+                    Some(expr) => self.eval(expr)?,
+                    None => Value::Nil,
+                };
+                Ok(Step::Yield(value))
+            }
+            Stmt::Block { stmts } => {
+                frames.push(Frame::Scope(self.env.clone()));
+                self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                frames.push(Frame::Stmts {
+                    stmts: Rc::new(stmts.clone()),
+                    next: 0,
+                });
+                Ok(Step::Continue)
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.eval(condition)?.is_truthy() {
+                    self.step(frames, then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.step(frames, else_branch)
+                } else {
+                    Ok(Step::Continue)
+                }
+            }
+            Stmt::While { condition, body } => {
+                frames.push(Frame::While {
+                    condition: Rc::new(condition.clone()),
+                    body: Rc::new((**body).clone()),
+                });
+                Ok(Step::Continue)
+            }
+            Stmt::ForIn {
+                name,
+                keyword,
+                iterable,
+                body,
+            } => {
+                let iterable = self.eval(iterable)?;
+                let iter = self.iterate(keyword, iterable)?;
+                frames.push(Frame::ForIn {
+                    name: name.lexeme.clone(),
+                    keyword: keyword.clone(),
+                    iter,
+                    body: Rc::new((**body).clone()),
+                });
+                Ok(Step::Continue)
+            }
+            Stmt::Match { value, cases, .. } => {
+                let value = self.eval(value)?;
+                for case in cases {
+                    let previous = self.env.clone();
+                    self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                    if self.case_matches(case, &value)? {
+                        frames.push(Frame::Scope(previous));
+                        return self.step(frames, &case.body);
+                    }
+                    self.env = previous;
+                }
+                Ok(Step::Continue)
+            }
+            // the resolver rejects a `yield` inside a `try`.
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{cell::RefCell, rc::Rc, vec};
+
+use super::{generator::Generator, Interpreter, Value};
+use crate::{
+    error::{Error, ErrorKind},
+    token::{Token, TokenType},
+};
+
+// where a `for-in` loop is in the value it walks.
+pub enum Iter {
+    // the list is read at every step, elements pushed in the loop are visited too.
+    List {
+        list: Rc<RefCell<Vec<Value>>>,
+        next: usize,
+    },
+    // the keys of a map or the characters of a string, taken when the loop starts.
+    Values(vec::IntoIter<Value>),
+    Range {
+        next: i64,
+        end: i64,
+        step: i64,
+    },
+    // the object `iterator()` returned, it has `hasNext()` and `next()` methods.
+    Object(Value),
+    Generator(Rc<Generator>),
+}
+
+impl Interpreter {
+    // start iterating a value, `token` is the `in` of the loop, for errors.
+    pub(super) fn iterate(&mut self, token: &Token, value: Value) -> Result<Iter, Error> {
+        match value {
+            Value::List(list) => Ok(Iter::List { list, next: 0 }),
+            Value::Map(map) => Ok(Iter::Values(map.borrow().keys().into_iter())),
+            Value::String(s) => Ok(Iter::Values(
+                s.chars()
+                    .map(|c| Value::String(c.to_string()))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )),
+            Value::Range(range) => Ok(Iter::Range {
+                next: range.start,
+                end: range.end,
+                step: range.step,
+            }),
+            Value::Generator(generator) => Ok(Iter::Generator(generator)),
+            Value::Instance(_) => {
+                let iterator = self.call_method(token, value, "iterator")?;
+                Ok(Iter::Object(iterator))
+            }
+            _ => Err(Error::RuntimeError(
+                token.clone(),
+                ErrorKind::Type,
+                "Can only iterate over lists, maps, strings, ranges and iterables.".to_string(),
+            )),
+        }
+    }
+
+    // the next value of the loop, `None` when it's done.
+    pub(super) fn next(&mut self, token: &Token, iter: &mut Iter) -> Result<Option<Value>, Error> {
+        // an iteration may evaluate nothing, e.g. a `for` with an empty body.
+        self.step_limit(token)?;
+        match iter {
+            Iter::List { list, next } => {
+                let value = list.borrow().get(*next).cloned();
+                *next += 1;
+                Ok(value)
+            }
+            Iter::Values(values) => Ok(values.next()),
+            Iter::Range { next, end, step } => {
+                let done = if *step > 0 {
+                    *next >= *end
+                } else {
+                    *next <= *end
+                };
+                if done {
+                    return Ok(None);
+                }
+                let value = *next;
+                // a range that would step past i64 ends there.
+                *next = next.checked_add(*step).unwrap_or(*end);
+                Ok(Some(Value::Integer(value)))
+            }
+            Iter::Object(iterator) => {
+                if !self
+                    .call_method(token, iterator.clone(), "hasNext")?
+                    .is_truthy()
+                {
+                    return Ok(None);
+                }
+                self.call_method(token, iterator.clone(), "next").map(Some)
+            }
+            Iter::Generator(generator) => self.resume(token, generator),
+        }
+    }
+
+    // `object.name()`
+    fn call_method(&mut self, token: &Token, object: Value, name: &str) -> Result<Value, Error> {
+        let name = Token::new(TokenType::IDENTIFIER, name, token.line, token.offset);
+        let method = self.get_property(&object, &name)?;
+        self.call_value(method, token, Vec::new())
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    cell::Cell,
+    hint,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use super::{value::OpError, Interpreter, Value};
+use crate::{
+    ast::Stmt,
+    error::{Error, ErrorKind},
+    token::Token,
+    Allocations, Config,
+};
+
+// reading the clock on every step would cost more than the step, so it's read every this many.
+const CLOCK_INTERVAL: u64 = 1024;
+
+thread_local! {
+    // the lowest address the stack of the interpreter running on this thread may grow to,
+    // 0 on a thread that hasn't started one.
+    static STACK_END: Cell<usize> = const { Cell::new(0) };
+}
+
+// the address of the top of the stack, it grows down.
+fn stack_address() -> usize {
+    let top = 0u8;
+    hint::black_box(&top) as *const u8 as usize
+}
+
+// what a script may use, shared by all the calls and modules of a run.
+pub(super) struct Limits {
+    pub max_depth: usize,
+    pub stack_size: usize, // of the interpreter's thread, a fiber's thread has as much.
+    steps: Cell<u64>,      // the statements and expressions evaluated so far.
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    next_clock: Cell<u64>, // the step to read the clock at.
+    interrupt: Option<Arc<AtomicBool>>,
+    exceeded: Cell<bool>,
+    max_memory: Option<usize>,
+    allocations: Allocations,
+}
+
+impl Limits {
+    pub fn new(config: &Config) -> Limits {
+        Limits {
+            max_depth: config.max_depth,
+            // `exec_file` has checked the stack fits.
+            stack_size: crate::stack_size(config.max_depth).unwrap_or(crate::MAX_STACK),
+            steps: Cell::new(0),
+            fuel: config.fuel,
+            deadline: config.timeout.map(|timeout| Instant::now() + timeout),
+            next_clock: Cell::new(CLOCK_INTERVAL),
+            interrupt: config.interrupt.clone(),
+            exceeded: Cell::new(false),
+            max_memory: config.max_memory,
+            allocations: config.allocations.clone(),
+        }
+    }
+
+    // count one step, `false` once the script has run out of fuel or time, or has been interrupted.
+    // it stays `false` after that, so nothing more of the script runs, not even a `finally`.
+    fn step(&self) -> bool {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if self.exceeded.get() {
+            return false;
+        }
+        let exceeded = self.fuel.is_some_and(|fuel| steps > fuel)
+            || self
+                .interrupt
+                .as_ref()
+                .is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+            || match self.deadline {
+                Some(deadline) if steps >= self.next_clock.get() => {
+                    self.next_clock.set(steps + CLOCK_INTERVAL);
+                    Instant::now() >= deadline
+                }
+                _ => false,
+            };
+        self.exceeded.set(exceeded);
+        !exceeded
+    }
+}
+
+impl Interpreter {
+    // the thread this is called on runs the script or a fiber, with a stack of `stack_size` bytes.
+    pub(super) fn enter_thread(&self) {
+        let stack_left = self.limits.stack_size.saturating_sub(crate::STACK_RESERVE);
+        STACK_END.set(stack_address().saturating_sub(stack_left));
+    }
+
+    // a call or expression nested deeper than the thread's stack holds, even below `max_depth`.
+    pub(super) fn check_stack(&self, token: &Token) -> Result<(), Error> {
+        if stack_address() >= STACK_END.get() {
+            return Ok(());
+        }
+        Err(Error::RuntimeError(
+            token.clone(),
+            ErrorKind::Runtime,
+            "Stack overflow.".to_string(),
+        ))
+    }
+
+    // `step_limit` for a statement. an empty block has no token for the error, it only counts:
+    // the limit stays exceeded, so the next step stops the script.
+    pub(super) fn count_step(&self, stmt: &Stmt) -> Result<(), Error> {
+        match stmt.token() {
+            Some(token) => self.step_limit(token),
+            None => {
+                self.limits.step();
+                Ok(())
+            }
+        }
+    }
+
+    // count the bytes of a new value, `token` is the expression that makes it.
+    pub(super) fn allocate(&self, token: &Token, bytes: usize) -> Result<(), Error> {
+        self.reserve(bytes)
+            .map_err(|(kind, msg)| Error::RuntimeError(token.clone(), kind, msg))
+    }
+
+    // `allocate` for the native functions, their errors are reported at the call.
+    pub(super) fn reserve(&self, bytes: usize) -> Result<(), OpError> {
+        let total = self.limits.allocations.add(bytes);
+        if self.limits.max_memory.is_some_and(|max| total > max) {
+            return Err((ErrorKind::Limit, "Out of memory.".to_string()));
+        }
+        Ok(())
+    }
+
+    // count the digits of a big number the arithmetic made.
+    pub(super) fn allocate_number(&self, token: &Token, value: &Value) -> Result<(), Error> {
+        match value {
+            Value::BigInt(n) => self.allocate(token, n.size()),
+            Value::Decimal(n) => self.allocate(token, n.size()),
+            _ => Ok(()),
+        }
+    }
+
+    // count a step and stop the script if it is over a limit.
+    pub(super) fn step_limit(&self, token: &Token) -> Result<(), Error> {
+        if self.limits.step() {
+            return Ok(());
+        }
+        Err(Error::RuntimeError(
+            token.clone(),
+            ErrorKind::Limit,
+            "Execution limit exceeded.".to_string(),
+        ))
+    }
+}
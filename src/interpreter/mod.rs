@@ -1,41 +1,85 @@
+mod collection;
+mod destructure;
 mod environment;
+mod fiber;
+mod generator;
+mod iterator;
+mod limit;
+mod module;
+mod native;
+mod overload;
 mod value;
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+pub(crate) use value::Value;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs, mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
-    ast::{Expr, Stmt},
-    error::Error,
+    ast::{Expr, MatchCase, Pattern, Stmt},
+    error::{Error, ErrorKind},
+    resolver::Resolution,
     token::{Token, TokenType},
+    Config,
 };
+use collection::MapValue;
 use environment::Environment;
-use value::{Clock, FuncValue, Value};
+use fiber::{Scheduler, Yielder};
+use limit::Limits;
+use module::{Module, Modules};
+use value::{Callable, ClassValue, ErrorValue, FuncValue, TraitValue};
 
-// Interpret the semantics of an ast.
-pub fn interpret(stmts: Vec<Stmt>, locations: HashMap<Expr, usize>) {
-    let mut i = Interpreter::new(locations);
+// Interpret the semantics of an ast, `path` is the file it comes from.
+pub fn interpret(stmts: Vec<Stmt>, resolution: Resolution, path: &Path, config: &Config) {
+    let mut i = Interpreter::new(resolution, path, config);
+    i.enter_thread();
     i.interpret(stmts);
 }
 
 #[derive(Clone)]
-struct Interpreter {
+pub(crate) struct Interpreter {
     env: Rc<RefCell<Environment>>, // track the current environment: variables, functions, &c.
-    globals: Rc<RefCell<Environment>>, // the global environment, e.g.: native functions.
-    locations: Rc<HashMap<Expr, usize>>,
+    module: Rc<Module>, // the file of the running code, its globals are the global environment.
+    modules: Rc<RefCell<Modules>>, // shared by all the files of a program.
+    tail_call: Option<TailCall>, // set by a `return f(x);`, the returning function makes the call.
+    depth: usize,       // the calls the running code is nested in, each call has its own copy.
+    limits: Rc<Limits>,
+    scheduler: Rc<Scheduler>, // the fibers `spawn` made, shared by all the files of a program.
+    fiber: Option<Rc<Yielder>>, // the fiber the code runs in, on that fiber's thread.
+}
+
+// a call in tail position, evaluated but not made yet.
+#[derive(Clone)]
+struct TailCall {
+    callee: Rc<dyn Callable>,
+    paren: Token,
+    args: Vec<Value>,
 }
 
 impl Interpreter {
-    fn new(locations: HashMap<Expr, usize>) -> Self {
-        let globals = Rc::new(RefCell::new(Environment::new()));
-        // add native function.
-        globals
-            .borrow_mut()
-            .define("clock".to_string(), Value::Callable(Rc::new(Clock {})));
+    fn new(resolution: Resolution, path: &Path, config: &Config) -> Self {
+        let module = Rc::new(Module::new(path.to_path_buf(), resolution));
+
+        // the main script is loading as long as it runs, a module importing it is a cycle.
+        let mut modules = Modules::new();
+        if let Ok(path) = fs::canonicalize(path) {
+            modules.insert(path, None);
+        }
 
         Interpreter {
-            env: globals.clone(),
-            globals,
-            locations: Rc::new(locations),
+            env: module.globals.clone(),
+            module,
+            modules: Rc::new(RefCell::new(modules)),
+            tail_call: None,
+            depth: 0,
+            limits: Rc::new(Limits::new(config)),
+            scheduler: Rc::new(Scheduler::default()),
+            fiber: None,
         }
     }
 
@@ -44,6 +88,10 @@ impl Interpreter {
             if let Err(e) = self.execute(stmt) {
                 // runtime error, interpreter will print it.
                 eprintln!("{}", e);
+                // over a limit the script stops, the statements after it don't run.
+                if let Error::RuntimeError(_, ErrorKind::Limit, _) = e {
+                    break;
+                }
             }
         }
     }
@@ -73,21 +121,38 @@ impl Interpreter {
     }
 
     fn execute(&mut self, stmt: &Stmt) -> Result<(Value, bool), Error> {
+        self.count_step(stmt)?;
         match stmt {
             Stmt::Expression { expr } => Ok((self.eval(expr)?, false)),
-            Stmt::Print { expr } => {
+            Stmt::Print { keyword, expr } => {
                 let v = self.eval(expr)?;
-                println!("{}", v);
+                let s = self.stringify(keyword, &v)?;
+                self.allocate(keyword, s.len())?;
+                println!("{}", s);
                 Ok((v, false))
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var {
+                name,
+                initializer,
+                constant,
+                ..
+            } => {
                 let value = match initializer {
                     Some(expr) => self.eval(expr)?,
                     None => Value::Nil,
                 };
-                self.env
-                    .borrow_mut()
-                    .define(name.lexeme.clone(), value.clone());
+                self.define_variable(name, value.clone(), *constant)?;
+                Ok((value, false))
+            }
+            Stmt::Destructure {
+                target,
+                initializer,
+                constant,
+            } => {
+                let value = self.eval(initializer)?;
+                for (name, value) in self.destructure(target, &value)? {
+                    self.define_variable(name, value, *constant)?;
+                }
                 Ok((value, false))
             }
             Stmt::Block { stmts } => {
@@ -113,30 +178,412 @@ impl Interpreter {
                 let mut res = (Value::Nil, false);
                 while self.eval(condition)?.is_truthy() {
                     res = self.execute(body)?;
+                    if res.1 {
+                        break;
+                    }
                 }
                 Ok(res)
             }
             Stmt::Return { keyword: _, value } => match value {
+                // the function makes the call once it has returned, its Rust frame is gone by then.
+                Some(
+                    expr @ Expr::Call {
+                        callee,
+                        paren,
+                        args,
+                    },
+                ) if self.module.tail_calls.contains(expr) => {
+                    let (callee, args) = self.prepare_call(callee, paren, args)?;
+                    self.tail_call = Some(TailCall {
+                        callee,
+                        paren: paren.clone(),
+                        args,
+                    });
+                    Ok((Value::Nil, true))
+                }
                 Some(expr) => Ok((self.eval(expr)?, true)),
                 None => Ok((Value::Nil, true)),
             },
-            Stmt::Function { name, params, body } => {
+            // a function with a `yield` runs as a generator, which runs its yields itself.
+            Stmt::Yield { .. } => unreachable!(),
+            Stmt::Function {
+                name, params, body, ..
+            } => {
+                self.allocate(name, mem::size_of::<FuncValue>())?;
                 let func = FuncValue::from(
                     name.clone(),
                     params.clone(),
                     (*body.clone()).to_vec(),
                     self.env.clone(),
+                    self.module.clone(),
+                    false,
                 );
+                self.define_variable(name, Value::Callable(Rc::new(func)), false)?;
+                Ok((Value::Nil, false))
+            }
+            Stmt::Trait { name, methods, .. } => {
+                let t = TraitValue {
+                    name: name.lexeme.clone(),
+                    methods: self.functions(methods, false),
+                };
+                self.define_variable(name, Value::Trait(Rc::new(t)), false)?;
+                Ok((Value::Nil, false))
+            }
+            Stmt::Class {
+                name,
+                super_class,
+                traits,
+                methods,
+                getters,
+                static_methods,
+                static_fields,
+                ..
+            } => {
+                let super_class = match super_class {
+                    Some(expr) => match self.eval(expr)? {
+                        Value::Class(class) => Some(class),
+                        _ => {
+                            let token = match expr {
+                                Expr::Variable { name } => name.clone(),
+                                _ => name.clone(),
+                            };
+                            return Err(Error::RuntimeError(
+                                token,
+                                ErrorKind::Type,
+                                "Superclass must be a class.".to_string(),
+                            ));
+                        }
+                    },
+                    None => None,
+                };
+                let mut trait_values = Vec::new();
+                for expr in traits {
+                    match (self.eval(expr)?, expr) {
+                        (Value::Trait(t), _) => trait_values.push(t),
+                        (_, Expr::Variable { name }) => {
+                            return Err(Error::RuntimeError(
+                                name.clone(),
+                                ErrorKind::Type,
+                                format!("'{}' is not a trait.", name.lexeme),
+                            ));
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+
+                // the methods close over a scope binding `super`, like the resolver assumes.
+                let previous = self.env.clone();
+                if let Some(super_class) = &super_class {
+                    self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                    self.env
+                        .borrow_mut()
+                        .define("super".to_string(), Value::Class(super_class.clone()));
+                }
+                let mut funcs = self.functions(methods, true);
+                let getters = self.functions(getters, false);
+                self.env = previous;
+
+                // the class's own methods win over its traits', which win over its superclass's.
+                let mut from: HashMap<&String, &String> = HashMap::new();
+                for t in &trait_values {
+                    for (method, func) in &t.methods {
+                        if funcs.contains_key(method) && !from.contains_key(method) {
+                            continue;
+                        }
+                        if let Some(other) = from.insert(method, &t.name) {
+                            return Err(Error::RuntimeError(
+                                name.clone(),
+                                ErrorKind::Type,
+                                format!(
+                                    "Method '{}' is defined by both traits '{}' and '{}'.",
+                                    method, other, t.name
+                                ),
+                            ));
+                        }
+                        funcs.insert(method.clone(), func.clone());
+                    }
+                }
+
+                // static methods have no `this` nor `super`, they close over the class declaration's env.
+                let statics = self
+                    .functions(static_methods, false)
+                    .into_iter()
+                    .map(|(name, func)| (name, Value::Callable(func as Rc<dyn Callable>)))
+                    .collect();
+                let class = Rc::new(ClassValue::new(
+                    name.lexeme.clone(),
+                    super_class,
+                    funcs,
+                    getters,
+                    statics,
+                    static_fields
+                        .iter()
+                        .map(|(field, _)| field.lexeme.clone())
+                        .collect(),
+                ));
+                self.define_variable(name, Value::Class(class.clone()), false)?;
+                // after the class is defined, so an initializer can use it: `static origin = Point(0, 0);`
+                for (field, value) in static_fields {
+                    let value = self.eval(value)?;
+                    class.set(field, value)?;
+                }
+                Ok((Value::Nil, false))
+            }
+            Stmt::ForIn {
+                name,
+                keyword,
+                iterable,
+                body,
+            } => {
+                let iterable = self.eval(iterable)?;
+                let mut iter = self.iterate(keyword, iterable)?;
+                while let Some(value) = self.next(keyword, &mut iter)? {
+                    // a fresh env every iteration, so closures in the body capture that iteration's value.
+                    let previous = self.env.clone();
+                    self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                    self.env.borrow_mut().define(name.lexeme.clone(), value);
+                    let res = self.execute(body);
+                    self.env = previous;
+                    if res.as_ref().map_or(true, |res| res.1) {
+                        return res;
+                    }
+                }
+                Ok((Value::Nil, false))
+            }
+            Stmt::Import {
+                keyword,
+                path,
+                name,
+            } => {
+                let module = self.import(keyword, path)?;
+                self.define_variable(name, Value::Module(module), false)?;
+                Ok((Value::Nil, false))
+            }
+            Stmt::Match { value, cases, .. } => {
+                let value = self.eval(value)?;
+                for case in cases {
+                    // the bindings of a case live in a new env, like a block's variables.
+                    let previous = self.env.clone();
+                    self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                    let res = self.run_case(case, &value);
+                    self.env = previous;
+                    if let Some(res) = res? {
+                        return Ok(res);
+                    }
+                }
+                // no case matches, nothing runs.
+                Ok((Value::Nil, false))
+            }
+            Stmt::Throw { keyword, value } => {
+                let value = self.eval(value)?;
+                Err(Error::Throw(keyword.clone(), value))
+            }
+            Stmt::Try {
+                body,
+                catch_clause,
+                finally_body,
+            } => {
+                let mut res = self.execute(body);
+                if let (Err(e), Some((name, catch_body))) = (&res, catch_clause) {
+                    if let Some(value) = Self::caught(e) {
+                        // the catch variable lives in its own scope around the catch block.
+                        let previous = self.env.clone();
+                        self.env = Rc::new(RefCell::new(Environment::from(&self.env)));
+                        self.env.borrow_mut().define(name.lexeme.clone(), value);
+                        res = self.execute(catch_body);
+                        self.env = previous;
+                    }
+                }
+                // `finally` always runs, if it throws or returns that wins over the try/catch outcome.
+                // but once over a limit nothing runs anymore.
+                if let Err(Error::RuntimeError(_, ErrorKind::Limit, _)) = res {
+                    return res;
+                }
+                if let Some(finally_body) = finally_body {
+                    let finally_res = self.execute(finally_body)?;
+                    if finally_res.1 {
+                        return Ok(finally_res);
+                    }
+                }
+                res
+            }
+        }
+    }
+
+    fn define_variable(&mut self, name: &Token, value: Value, constant: bool) -> Result<(), Error> {
+        if self.env.borrow().is_const(&name.lexeme) {
+            return Err(Error::RuntimeError(
+                name.clone(),
+                ErrorKind::Runtime,
+                format!("Can't redeclare constant '{}'.", name.lexeme),
+            ));
+        }
+        let mut env = self.env.borrow_mut();
+        match constant {
+            true => env.define_const(name.lexeme.clone(), value),
+            false => env.define(name.lexeme.clone(), value),
+        }
+        Ok(())
+    }
+
+    // run the body of a case if one of its patterns matches the value and its guard holds.
+    fn run_case(
+        &mut self,
+        case: &MatchCase,
+        value: &Value,
+    ) -> Result<Option<(Value, bool)>, Error> {
+        if !self.case_matches(case, value)? {
+            return Ok(None);
+        }
+        self.execute(&case.body).map(Some)
+    }
+
+    // whether one of the patterns of a case matches the value and its guard holds.
+    fn case_matches(&mut self, case: &MatchCase, value: &Value) -> Result<bool, Error> {
+        for pattern in &case.patterns {
+            if !self.match_pattern(pattern, value)? {
+                continue;
+            }
+            if let Some(guard) = &case.guard {
+                if !self.eval(guard)?.is_truthy() {
+                    continue;
+                }
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // test a value against a pattern, binding the names of the pattern in the current env.
+    fn match_pattern(&mut self, pattern: &Pattern, value: &Value) -> Result<bool, Error> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Literal { value: literal } => Ok(self.eval(literal)? == *value),
+            Pattern::Binding { name } => {
                 self.env
                     .borrow_mut()
-                    .define(name.lexeme.clone(), Value::Callable(Rc::new(func)));
-                Ok((Value::Nil, false))
+                    .define(name.lexeme.clone(), value.clone());
+                Ok(true)
+            }
+            Pattern::Instance { class, fields } => {
+                let class = match (self.eval(class)?, class) {
+                    (Value::Class(class), _) => class,
+                    (_, Expr::Variable { name }) => {
+                        return Err(Error::RuntimeError(
+                            name.clone(),
+                            ErrorKind::Type,
+                            format!("'{}' is not a class.", name.lexeme),
+                        ))
+                    }
+                    _ => unreachable!(),
+                };
+                let instance = match value {
+                    Value::Instance(instance) if instance.is_instance_of(&class) => instance,
+                    _ => return Ok(false),
+                };
+                for (name, pattern) in fields {
+                    match instance.field(&name.lexeme) {
+                        Some(field) if self.match_pattern(pattern, &field)? => (),
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            Pattern::List {
+                bracket,
+                elements,
+                rest,
+            } => {
+                let list = match value {
+                    Value::List(list) => list.borrow().clone(),
+                    _ => return Ok(false),
+                };
+                let fits = match rest {
+                    Some(_) => list.len() >= elements.len(),
+                    None => list.len() == elements.len(),
+                };
+                if !fits {
+                    return Ok(false);
+                }
+                for (pattern, element) in elements.iter().zip(&list) {
+                    if !self.match_pattern(pattern, element)? {
+                        return Ok(false);
+                    }
+                }
+                if let Some(rest) = rest.as_ref().filter(|rest| rest.lexeme != "_") {
+                    let others = list[elements.len()..].to_vec();
+                    self.allocate(bracket, others.len() * mem::size_of::<Value>())?;
+                    self.env.borrow_mut().define(
+                        rest.lexeme.clone(),
+                        Value::List(Rc::new(RefCell::new(others))),
+                    );
+                }
+                Ok(true)
             }
-            _ => todo!(),
+        }
+    }
+
+    // load a module the first time it's imported: scan, parse, resolve and run it in a global
+    // scope of its own. later imports of the same file, by any path, share the loaded module.
+    fn import(&mut self, keyword: &Token, path: &str) -> Result<Rc<Module>, Error> {
+        let import_error =
+            |msg: String| Error::RuntimeError(keyword.clone(), ErrorKind::Import, msg);
+
+        let file: PathBuf = fs::canonicalize(self.module.dir().join(path))
+            .map_err(|_| import_error(format!("Cannot find module '{}'.", path)))?;
+        match self.modules.borrow().get(&file) {
+            Some(Some(module)) => return Ok(module.clone()),
+            Some(None) => return Err(import_error(format!("Circular import of '{}'.", path))),
+            None => (),
+        }
+
+        let src = fs::read_to_string(&file)
+            .map_err(|_| import_error(format!("Cannot read module '{}'.", path)))?;
+        // the scan, parse and resolve errors have been print, as for the main script.
+        let compile_error = || import_error(format!("Cannot compile module '{}'.", path));
+        let stmts = crate::parse(src).ok_or_else(compile_error)?;
+        let resolution = crate::resolver::resolve_variable(&stmts).ok_or_else(compile_error)?;
+
+        let module = Rc::new(Module::new(file.clone(), resolution));
+        self.modules.borrow_mut().insert(file.clone(), None);
+        let mut i = Interpreter {
+            env: module.globals.clone(),
+            module: module.clone(),
+            modules: self.modules.clone(),
+            tail_call: None,
+            depth: self.depth,
+            limits: self.limits.clone(),
+            scheduler: self.scheduler.clone(),
+            fiber: self.fiber.clone(),
+        };
+        if let Err(e) = i.execute_stmts(&stmts) {
+            // the module isn't loaded, a later import tries again.
+            self.modules.borrow_mut().remove(&file);
+            return Err(e);
+        }
+        self.modules.borrow_mut().insert(file, Some(module.clone()));
+        Ok(module)
+    }
+
+    // the value a `catch` clause binds for an error: a thrown value as is,
+    // a runtime error as an error value with its message, line and kind.
+    fn caught(e: &Error) -> Option<Value> {
+        match e {
+            Error::Throw(_, value) => Some(value.clone()),
+            // a script can't catch its way past a limit of the host.
+            Error::RuntimeError(_, ErrorKind::Limit, _) => None,
+            Error::RuntimeError(token, kind, msg) => Some(Value::Error(Rc::new(ErrorValue {
+                message: msg.clone(),
+                line: token.line,
+                kind: *kind,
+            }))),
+            Error::ParseError(..) | Error::ShortCircuit => None,
         }
     }
 
     fn eval(&mut self, expr: &Expr) -> Result<Value, Error> {
+        self.step_limit(expr.token())?;
+        self.check_stack(expr.token())?;
         match expr {
             Expr::Literal { .. } => self.eval_literal(expr),
             Expr::Grouping { expression } => self.eval(expression),
@@ -144,9 +591,87 @@ impl Interpreter {
             Expr::Binary { .. } => self.eval_binary(expr),
             Expr::Variable { .. } => self.eval_variable(expr),
             Expr::Assign { .. } => self.eval_assign(expr),
+            Expr::AssignList {
+                bracket,
+                targets,
+                value,
+            } => {
+                let value = self.eval(value)?;
+                let values = self.elements(bracket, &value, targets.len(), false)?;
+                for (target, value) in targets.iter().zip(values) {
+                    self.assign_target(target, value)?;
+                }
+                Ok(value)
+            }
             Expr::Logical { .. } => self.eval_logical(expr),
             Expr::Call { .. } => self.eval_call(expr),
-            _ => todo!(),
+            Expr::Get { .. } => self.eval_get(expr),
+            Expr::OptionalGet { object, name } => match self.eval(object)? {
+                Value::Nil => Err(Error::ShortCircuit),
+                object => self.get_property(&object, name),
+            },
+            Expr::FiberYield { keyword, value } => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Nil,
+                };
+                self.fiber_yield(keyword, value)
+            }
+            Expr::OptionalChain { chain } => match self.eval(chain) {
+                Err(Error::ShortCircuit) => Ok(Value::Nil),
+                res => res,
+            },
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => match self.eval(condition)?.is_truthy() {
+                true => self.eval(then_expr),
+                false => self.eval(else_expr),
+            },
+            Expr::Set { .. } => self.eval_set(expr),
+            Expr::List { bracket, elements } => {
+                let values = self.eval_args(elements)?;
+                self.allocate(bracket, values.len() * mem::size_of::<Value>())?;
+                Ok(Value::List(Rc::new(RefCell::new(values))))
+            }
+            Expr::Spread { .. } => unreachable!("spread is only parsed in arguments and lists"),
+            Expr::Map { brace, entries } => {
+                let mut map = MapValue::default();
+                for (key, value) in entries {
+                    let key = self.eval(key)?;
+                    let value = self.eval(value)?;
+                    map.insert(key, value)
+                        .map_err(|(kind, msg)| Error::RuntimeError(brace.clone(), kind, msg))?;
+                }
+                self.allocate(brace, entries.len() * 2 * mem::size_of::<Value>())?;
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.eval(object)?;
+                let index = self.eval(index)?;
+                self.get_index(bracket, &object, &index)
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.eval(object)?;
+                let index = self.eval(index)?;
+                let value = self.eval(value)?;
+                self.set_index(bracket, &object, &index, value.clone())?;
+                Ok(value)
+            }
+            Expr::CompoundAssign { .. } => self.eval_compound_assign(expr),
+            Expr::Increment { .. } => self.eval_increment(expr),
+            Expr::This { keyword } => self.lookup_variable(keyword, expr),
+            Expr::Super { .. } => self.eval_super(expr),
         }
     }
 
@@ -156,11 +681,15 @@ impl Interpreter {
                 TokenType::TRUE => return Ok(Value::Bool(true)),
                 TokenType::FALSE => return Ok(Value::Bool(false)),
                 TokenType::NIL => return Ok(Value::Nil),
+                TokenType::INTEGER { literal } => return Ok(Value::Integer(*literal)),
+                TokenType::BIGINT { literal } => return Ok(Value::BigInt(literal.clone())),
+                TokenType::DECIMAL { literal } => return Ok(Value::Decimal(literal.clone())),
                 TokenType::NUMBER { literal } => return Ok(Value::Number(literal.0)),
                 TokenType::STRING { literal } => return Ok(Value::String(literal.clone())),
                 _ => {
                     return Err(Error::RuntimeError(
                         value.clone(),
+                        ErrorKind::Runtime,
                         "Expr::Literal error".to_string(),
                     ))
                 }
@@ -172,12 +701,29 @@ impl Interpreter {
     fn eval_unary(&mut self, expr: &Expr) -> Result<Value, Error> {
         if let Expr::Unary { op, right } = expr {
             let right = self.eval(right)?;
+            if op.t == TokenType::MINUS {
+                if let Some(res) = self.negate(op, &right)? {
+                    return Ok(res);
+                }
+            }
             match (&op.t, &right) {
-                (TokenType::MINUS, Value::Number(num)) => return Ok(Value::Number(-num)),
+                (TokenType::MINUS, _) => {
+                    let value = right
+                        .neg()
+                        .map_err(|(kind, msg)| Error::RuntimeError(op.clone(), kind, msg))?;
+                    self.allocate_number(op, &value)?;
+                    return Ok(value);
+                }
+                (TokenType::TILDE, _) => {
+                    return right
+                        .bit_not()
+                        .map_err(|(kind, msg)| Error::RuntimeError(op.clone(), kind, msg))
+                }
                 (TokenType::BANG, _) => return Ok(Value::Bool(!right.is_truthy())),
                 _ => {
                     return Err(Error::RuntimeError(
                         op.clone(),
+                        ErrorKind::Type,
                         format!("Expr::Unary's op {} and right {} is mismatch", op, right),
                     ))
                 }
@@ -190,41 +736,7 @@ impl Interpreter {
         if let Expr::Binary { left, op, right } = expr {
             let left = self.eval(left)?;
             let right = self.eval(right)?;
-            match &op.t {
-                TokenType::MINUS => match left - right {
-                    Ok(res) => return Ok(res),
-                    Err(e) => return Err(Error::RuntimeError(op.clone(), e)),
-                },
-                TokenType::PLUS => match left + right {
-                    Ok(res) => return Ok(res),
-                    Err(e) => return Err(Error::RuntimeError(op.clone(), e)),
-                },
-                TokenType::SLASH => match left / right {
-                    Ok(res) => return Ok(res),
-                    Err(e) => return Err(Error::RuntimeError(op.clone(), e)),
-                },
-                TokenType::STAR => match left * right {
-                    Ok(res) => return Ok(res),
-                    Err(e) => return Err(Error::RuntimeError(op.clone(), e)),
-                },
-                TokenType::GREATER => return Ok(left.gt(&right)),
-                TokenType::GreaterEqual => return Ok(left.ge(&right)),
-                TokenType::LESS => return Ok(left.lt(&right)),
-                TokenType::LessEqual => return Ok(left.le(&right)),
-                TokenType::BangEqual => return Ok(Value::Bool(left != right)),
-                TokenType::EqualEqual => return Ok(Value::Bool(left == right)),
-                _ => {
-                    return Err(Error::RuntimeError(
-                        op.clone(),
-                        format!(
-                            "Expr:Binary's op {} and left {} or right {} is mismatch",
-                            op.clone(),
-                            left,
-                            right
-                        ),
-                    ));
-                }
-            }
+            return self.binary(op, left, right);
         }
         unreachable!()
     }
@@ -237,31 +749,88 @@ impl Interpreter {
     }
 
     fn lookup_variable(&self, name: &Token, expr: &Expr) -> Result<Value, Error> {
-        match self.locations.get(expr) {
+        match self.module.locations.get(expr) {
             Some(distance) => self.env.borrow().get_at(*distance, &name.lexeme),
-            None => self.globals.borrow().get(name),
+            None => self.module.globals.borrow().get(name),
         }
     }
 
     fn eval_assign(&mut self, expr: &Expr) -> Result<Value, Error> {
         if let Expr::Assign { name, value } = expr {
             let value = self.eval(value)?;
-            match self.locations.get(expr) {
-                Some(distance) => {
-                    self.env
-                        .borrow_mut()
-                        .assign_at(*distance, name.clone(), value.clone())?
-                }
-                None => self
-                    .globals
-                    .borrow_mut()
-                    .assign(name.clone(), value.clone())?,
-            }
+            self.assign_variable(name, expr, value.clone())?;
             return Ok(value);
         }
         unreachable!()
     }
 
+    // `expr` is the expression the resolver recorded the variable's depth for.
+    fn assign_variable(&mut self, name: &Token, expr: &Expr, value: Value) -> Result<(), Error> {
+        match self.module.locations.get(expr) {
+            Some(distance) => self
+                .env
+                .borrow_mut()
+                .assign_at(*distance, name.clone(), value),
+            None => self.module.globals.borrow_mut().assign(name.clone(), value),
+        }
+    }
+
+    fn eval_compound_assign(&mut self, expr: &Expr) -> Result<Value, Error> {
+        if let Expr::CompoundAssign { target, op, value } = expr {
+            let (_, new) = self.update(target, |i, old| {
+                let value = i.eval(value)?;
+                i.binary(op, old, value)
+            })?;
+            return Ok(new);
+        }
+        unreachable!()
+    }
+
+    fn eval_increment(&mut self, expr: &Expr) -> Result<Value, Error> {
+        if let Expr::Increment { target, op, prefix } = expr {
+            let (old, new) = self.update(target, |i, old| i.binary(op, old, Value::Integer(1)))?;
+            return Ok(if *prefix { new } else { old });
+        }
+        unreachable!()
+    }
+
+    // read a variable or a property, compute its new value and write it back, returning the old and new values.
+    // the object of a property is evaluated once: `a.b().c += 1` calls `b()` once.
+    fn update(
+        &mut self,
+        target: &Expr,
+        compute: impl FnOnce(&mut Self, Value) -> Result<Value, Error>,
+    ) -> Result<(Value, Value), Error> {
+        match target {
+            Expr::Variable { name } => {
+                let old = self.lookup_variable(name, target)?;
+                let new = compute(self, old.clone())?;
+                self.assign_variable(name, target, new.clone())?;
+                Ok((old, new))
+            }
+            Expr::Get { object, name } => {
+                let object = self.eval(object)?;
+                let old = self.get_property(&object, name)?;
+                let new = compute(self, old.clone())?;
+                Self::set_property(&object, name, new.clone())?;
+                Ok((old, new))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.eval(object)?;
+                let index = self.eval(index)?;
+                let old = self.get_index(bracket, &object, &index)?;
+                let new = compute(self, old.clone())?;
+                self.set_index(bracket, &object, &index, new.clone())?;
+                Ok((old, new))
+            }
+            _ => unreachable!(),
+        }
+    }
+
     // Lox 对 logical or/and 的语义和常见语言不同，比如:
     // - print "hi" or 2;
     //   常见语言: print true
@@ -272,7 +841,11 @@ impl Interpreter {
     fn eval_logical(&mut self, expr: &Expr) -> Result<Value, Error> {
         if let Expr::Logical { left, op, right } = expr {
             let left = self.eval(left)?;
-            if op.t == TokenType::OR {
+            if op.t == TokenType::QuestionQuestion {
+                if left != Value::Nil {
+                    return Ok(left);
+                }
+            } else if op.t == TokenType::OR {
                 if left.is_truthy() {
                     return Ok(left);
                 }
@@ -287,6 +860,139 @@ impl Interpreter {
         unreachable!()
     }
 
+    fn eval_get(&mut self, expr: &Expr) -> Result<Value, Error> {
+        if let Expr::Get { object, name } = expr {
+            let object = self.eval(object)?;
+            return self.get_property(&object, name);
+        }
+        unreachable!()
+    }
+
+    fn eval_set(&mut self, expr: &Expr) -> Result<Value, Error> {
+        if let Expr::Set {
+            object,
+            name,
+            value,
+        } = expr
+        {
+            let object = self.eval(object)?;
+            let value = self.eval(value)?;
+            Self::set_property(&object, name, value.clone())?;
+            return Ok(value);
+        }
+        unreachable!()
+    }
+
+    fn eval_super(&mut self, expr: &Expr) -> Result<Value, Error> {
+        if let Expr::Super { keyword: _, method } = expr {
+            // `this` is bound in the env right inside the one binding `super`.
+            let distance = self.module.locations[expr];
+            let super_class = self.env.borrow().get_at(distance, &"super".to_string())?;
+            let instance = self
+                .env
+                .borrow()
+                .get_at(distance - 1, &"this".to_string())?;
+            if let (Value::Class(super_class), Value::Instance(instance)) = (super_class, instance)
+            {
+                return match super_class.find_method(&method.lexeme) {
+                    Some(func) => Ok(Value::Callable(Rc::new(func.bind(instance)))),
+                    None => Err(Error::RuntimeError(
+                        method.clone(),
+                        ErrorKind::Name,
+                        format!("Undefined property '{}'.", method.lexeme),
+                    )),
+                };
+            }
+        }
+        unreachable!()
+    }
+
+    // the functions declared in a class body, closing over the current env.
+    // `initializer` is whether an `init` among them is the class's initializer.
+    fn functions(&self, stmts: &[Stmt], initializer: bool) -> HashMap<String, Rc<FuncValue>> {
+        let mut funcs = HashMap::new();
+        for stmt in stmts {
+            if let Stmt::Function {
+                name, params, body, ..
+            } = stmt
+            {
+                let func = FuncValue::from(
+                    name.clone(),
+                    params.clone(),
+                    body.clone(),
+                    self.env.clone(),
+                    self.module.clone(),
+                    initializer && name.lexeme == "init",
+                );
+                funcs.insert(name.lexeme.clone(), Rc::new(func));
+            }
+        }
+        funcs
+    }
+
+    fn get_property(&mut self, object: &Value, name: &Token) -> Result<Value, Error> {
+        match object {
+            Value::Instance(instance) => match instance.getter(&name.lexeme) {
+                Some(getter) => self.call(Rc::new(getter), name, Vec::new()),
+                None => instance.get(name),
+            },
+            Value::Class(class) => class.get(name),
+            Value::Generator(g) => generator::get_property(g, &name.lexeme).ok_or_else(|| {
+                Error::RuntimeError(
+                    name.clone(),
+                    ErrorKind::Name,
+                    format!("Undefined property '{}'.", name.lexeme),
+                )
+            }),
+            Value::Fiber(_) | Value::Channel(_) => fiber::get_property(object, &name.lexeme)
+                .ok_or_else(|| {
+                    Error::RuntimeError(
+                        name.clone(),
+                        ErrorKind::Name,
+                        format!("Undefined property '{}'.", name.lexeme),
+                    )
+                }),
+            Value::Error(e) => e.get(&name.lexeme).ok_or_else(|| {
+                Error::RuntimeError(
+                    name.clone(),
+                    ErrorKind::Name,
+                    format!("Undefined property '{}'.", name.lexeme),
+                )
+            }),
+            // a module exposes its top-level variables.
+            Value::Module(m) => m.globals.borrow().get(name).map_err(|_| {
+                Error::RuntimeError(
+                    name.clone(),
+                    ErrorKind::Name,
+                    format!("Undefined property '{}'.", name.lexeme),
+                )
+            }),
+            // `length` and the methods of the built-in values.
+            _ => collection::get_property(object, &name.lexeme).ok_or_else(|| {
+                Error::RuntimeError(
+                    name.clone(),
+                    ErrorKind::Type,
+                    "Only instances have properties.".to_string(),
+                )
+            }),
+        }
+    }
+
+    fn set_property(object: &Value, name: &Token, value: Value) -> Result<(), Error> {
+        match object {
+            Value::Instance(instance) => {
+                instance.set(name, value);
+                Ok(())
+            }
+            Value::Class(class) => class.set(name, value),
+            _ => Err(Error::RuntimeError(
+                name.clone(),
+                ErrorKind::Type,
+                "Only instances have fields.".to_string(),
+            )),
+        }
+    }
+
     fn eval_call(&mut self, expr: &Expr) -> Result<Value, Error> {
         if let Expr::Call {
             callee,
@@ -294,34 +1000,112 @@ impl Interpreter {
             args,
         } = expr
         {
-            let callee = match self.eval(callee)? {
-                Value::Callable(callee) => callee.clone(),
-                _ => {
-                    return Err(Error::RuntimeError(
-                        paren.clone(),
-                        "Can only call functions and classes.".to_string(),
-                    ))
-                }
-            };
+            let (callee, args) = self.prepare_call(callee, paren, args)?;
+            return self.call(callee, paren, args);
+        }
+        unreachable!()
+    }
 
-            if args.len() != callee.arity() {
-                return Err(Error::RuntimeError(
-                    paren.clone(),
-                    format!(
-                        "Expected {} arguments but got {}.",
-                        callee.arity(),
-                        args.len()
-                    ),
-                ));
+    // evaluate the callee and the arguments of a call and check they fit.
+    fn prepare_call(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        args: &[Expr],
+    ) -> Result<(Rc<dyn Callable>, Vec<Value>), Error> {
+        let callee = match callee {
+            // `rect.area(2)` would call what the getter returns, it's most likely a mistake.
+            Expr::Get { object, name } if !args.is_empty() => {
+                let object = self.eval(object)?;
+                if let Value::Instance(instance) = &object {
+                    if instance.getter(&name.lexeme).is_some() {
+                        return Err(Error::RuntimeError(
+                            name.clone(),
+                            ErrorKind::Type,
+                            format!("Getter '{}' can't be called with arguments.", name.lexeme),
+                        ));
+                    }
+                }
+                self.get_property(&object, name)?
             }
+            _ => self.eval(callee)?,
+        };
+        let callee = Self::callable(callee, paren)?;
+        // a spread argument counts as its elements.
+        let args = self.eval_args(args)?;
+        Self::check_arity(&callee, paren, args.len())?;
+        Ok((callee, args))
+    }
 
-            let mut arg_values = Vec::new();
-            for arg in args {
-                arg_values.push(self.eval(arg)?);
+    // the values of call arguments or list elements, `...xs` adds every element of an iterable.
+    fn eval_args(&mut self, args: &[Expr]) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::new();
+        for arg in args {
+            if let Expr::Spread { dots, value } = arg {
+                let value = self.eval(value)?;
+                let mut iter = self.iterate(dots, value)?;
+                while let Some(value) = self.next(dots, &mut iter)? {
+                    values.push(value);
+                }
+            } else {
+                values.push(self.eval(arg)?);
             }
+        }
+        Ok(values)
+    }
 
-            return callee.call(self.clone(), arg_values);
+    // call a value from the interpreter itself, e.g. the methods of the iterator protocol.
+    fn call_value(
+        &mut self,
+        callee: Value,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let callee = Self::callable(callee, paren)?;
+        Self::check_arity(&callee, paren, args.len())?;
+        self.call(callee, paren, args)
+    }
+
+    // every call goes through here, one level deeper than the caller.
+    fn call(
+        &mut self,
+        callee: Rc<dyn Callable>,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        if self.depth >= self.limits.max_depth {
+            return Err(Error::RuntimeError(
+                paren.clone(),
+                ErrorKind::Runtime,
+                "Stack overflow.".to_string(),
+            ));
         }
-        unreachable!()
+        self.check_stack(paren)?;
+        let mut i = self.clone();
+        i.depth += 1;
+        callee.call(i, paren, args)
+    }
+
+    fn callable(callee: Value, paren: &Token) -> Result<Rc<dyn Callable>, Error> {
+        match callee {
+            Value::Callable(callee) => Ok(callee),
+            Value::Class(class) => Ok(class),
+            _ => Err(Error::RuntimeError(
+                paren.clone(),
+                ErrorKind::Type,
+                "Can only call functions and classes.".to_string(),
+            )),
+        }
+    }
+
+    fn check_arity(callee: &Rc<dyn Callable>, paren: &Token, count: usize) -> Result<(), Error> {
+        if !callee.arity().accepts(count) {
+            return Err(Error::RuntimeError(
+                paren.clone(),
+                ErrorKind::Arity,
+                format!("Expected {} arguments but got {}.", callee.arity(), count),
+            ));
+        }
+        Ok(())
     }
 }
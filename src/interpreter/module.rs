@@ -0,0 +1,83 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{ast::Expr, resolver::Resolution};
+
+use super::{
+    environment::Environment,
+    fiber,
+    native::{self, NativeFn, NativeFnPtr},
+    value::{Arity, Clock},
+    Value,
+};
+
+// a source file being run: the main script or an imported module.
+// every file has its own global scope and variable depths, and imports in it are relative to its directory.
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub globals: Rc<RefCell<Environment>>, // the top-level variables, an importer reads them as `name.variable`.
+    pub locations: HashMap<Expr, usize>,
+    pub tail_calls: HashSet<Expr>,
+}
+
+// the loaded modules by canonical path, `None` while a module is still being loaded,
+// so importing it again means a circular import.
+pub type Modules = HashMap<PathBuf, Option<Rc<Module>>>;
+
+impl Module {
+    pub fn new(path: PathBuf, resolution: Resolution) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        // add native functions.
+        globals
+            .borrow_mut()
+            .define("clock".to_string(), Value::Callable(Rc::new(Clock {})));
+        globals.borrow_mut().define(
+            "range".to_string(),
+            Value::Callable(Rc::new(NativeFn::global(
+                "range",
+                Arity::range(2, 3),
+                native::range,
+            ))),
+        );
+
+        globals
+            .borrow_mut()
+            .define("str".to_string(), Value::Callable(Rc::new(native::Str)));
+        // fibers and the scheduler running them.
+        let natives: [(&str, Arity, NativeFnPtr); 4] = [
+            ("Fiber", Arity::exact(1), fiber::new_fiber),
+            ("spawn", Arity::exact(1), fiber::spawn),
+            ("sleep", Arity::exact(1), fiber::sleep),
+            ("Channel", Arity::exact(0), fiber::new_channel),
+        ];
+        for (name, arity, func) in natives {
+            globals.borrow_mut().define(
+                name.to_string(),
+                Value::Callable(Rc::new(NativeFn::global(name, arity, func))),
+            );
+        }
+        globals
+            .borrow_mut()
+            .define("run".to_string(), Value::Callable(Rc::new(fiber::Run)));
+
+        Module {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path,
+            globals,
+            locations: resolution.depths,
+            tail_calls: resolution.tail_calls,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+}
@@ -0,0 +1,98 @@
+use std::rc::Rc;
+
+use super::{
+    collection::Range,
+    value::{type_error, Arity, Callable, OpError},
+    Interpreter, Value,
+};
+use crate::{
+    error::{Error, ErrorKind},
+    token::Token,
+};
+
+// a function written in Rust: a global like `range`, or a method of a built-in value like `list.push`,
+// bound to the value it's called on.
+pub struct NativeFn {
+    name: &'static str,
+    arity: Arity,
+    this: Value, // nil for a global
+    func: NativeFnPtr,
+}
+
+// the interpreter is there for the scheduler of the fibers and the memory a function allocates.
+pub(super) type NativeFnPtr = fn(&Interpreter, &Value, Vec<Value>) -> Result<Value, OpError>;
+
+impl NativeFn {
+    pub fn global(name: &'static str, arity: Arity, func: NativeFnPtr) -> Self {
+        Self::method(name, arity, Value::Nil, func)
+    }
+
+    pub fn method(name: &'static str, arity: Arity, this: Value, func: NativeFnPtr) -> Self {
+        NativeFn {
+            name,
+            arity,
+            this,
+            func,
+        }
+    }
+}
+
+impl Callable for NativeFn {
+    // a native function has no tokens of its own, its errors are reported at the call.
+    fn call(
+        self: Rc<Self>,
+        i: Interpreter,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        (self.func)(&i, &self.this, args)
+            .map_err(|(kind, msg)| Error::RuntimeError(paren.clone(), kind, msg))
+    }
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+    fn to_string(&self) -> String {
+        format!("<native fn {}>", self.name)
+    }
+}
+
+// str(value): the value as `print` shows it, honoring `__str__`.
+// it calls back into the interpreter, so it isn't a `NativeFn`.
+pub struct Str;
+
+impl Callable for Str {
+    fn call(
+        self: Rc<Self>,
+        mut i: Interpreter,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let s = i.stringify(paren, &args[0])?;
+        i.allocate(paren, s.len())?;
+        Ok(Value::String(s))
+    }
+    fn arity(&self) -> Arity {
+        Arity::exact(1)
+    }
+    fn to_string(&self) -> String {
+        String::from("<native fn str>")
+    }
+}
+
+// range(start, end, step = 1)
+pub fn range(_i: &Interpreter, _this: &Value, args: Vec<Value>) -> Result<Value, OpError> {
+    match args[..] {
+        [Value::Integer(start), Value::Integer(end)] => Ok(Value::Range(Range {
+            start,
+            end,
+            step: 1,
+        })),
+        [Value::Integer(_), Value::Integer(_), Value::Integer(0)] => {
+            Err((ErrorKind::Runtime, "Range step can't be zero.".to_string()))
+        }
+        [Value::Integer(start), Value::Integer(end), Value::Integer(step)] => {
+            Ok(Value::Range(Range { start, end, step }))
+        }
+        _ => Err(type_error("Range bounds and step must be integers.")),
+    }
+}
@@ -0,0 +1,167 @@
+use std::{mem, rc::Rc};
+
+use super::{collection, Interpreter, Value};
+use crate::{
+    error::Error,
+    token::{Token, TokenType},
+};
+
+// the method a class defines to overload an operator: `a + b` calls `a.__add__(b)`.
+// `+=` and `++` are additions too.
+fn special_method(op: &TokenType) -> Option<&'static str> {
+    match op {
+        TokenType::PLUS | TokenType::PlusEqual | TokenType::PlusPlus => Some("__add__"),
+        TokenType::MINUS | TokenType::MinusEqual | TokenType::MinusMinus => Some("__sub__"),
+        TokenType::STAR | TokenType::StarEqual => Some("__mul__"),
+        TokenType::SLASH | TokenType::SlashEqual => Some("__div__"),
+        TokenType::PERCENT | TokenType::PercentEqual => Some("__mod__"),
+        TokenType::LESS => Some("__lt__"),
+        TokenType::LessEqual => Some("__le__"),
+        TokenType::GREATER => Some("__gt__"),
+        TokenType::GreaterEqual => Some("__ge__"),
+        TokenType::EqualEqual | TokenType::BangEqual => Some("__eq__"),
+        _ => None,
+    }
+}
+
+impl Interpreter {
+    // call `object.name(args)` if the object is an instance whose class defines the method,
+    // `None` when it doesn't and the built-in behavior applies.
+    fn call_special(
+        &mut self,
+        token: &Token,
+        object: &Value,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Option<Value>, Error> {
+        let Value::Instance(instance) = object else {
+            return Ok(None);
+        };
+        match instance.method(name) {
+            Some(method) => {
+                let method = Value::Callable(Rc::new(method));
+                self.call_value(method, token, args).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    // a binary operator, on instances it calls the special method of the left operand.
+    pub(super) fn binary(&mut self, op: &Token, left: Value, right: Value) -> Result<Value, Error> {
+        if let Some(name) = special_method(&op.t) {
+            if let Some(res) = self.call_special(op, &left, name, vec![right.clone()])? {
+                return Ok(match op.t {
+                    TokenType::EqualEqual => Value::Bool(res.is_truthy()),
+                    TokenType::BangEqual => Value::Bool(!res.is_truthy()),
+                    _ => res,
+                });
+            }
+        }
+        // a concatenation counts before it's built, so doubling a big string fails without the memory.
+        if let (Some("__add__"), Value::String(l), Value::String(r)) =
+            (special_method(&op.t), &left, &right)
+        {
+            self.allocate(op, l.len() + r.len())?;
+        }
+        let value = Value::binary(&op.t, left, right)
+            .map_err(|(kind, msg)| Error::RuntimeError(op.clone(), kind, msg))?;
+        self.allocate_number(op, &value)?;
+        Ok(value)
+    }
+
+    // `-a` calls `a.__neg__()`.
+    pub(super) fn negate(&mut self, op: &Token, right: &Value) -> Result<Option<Value>, Error> {
+        self.call_special(op, right, "__neg__", Vec::new())
+    }
+
+    // `object[index]`, `__index__(index)` on an instance.
+    pub(super) fn get_index(
+        &mut self,
+        bracket: &Token,
+        object: &Value,
+        index: &Value,
+    ) -> Result<Value, Error> {
+        if let Some(value) = self.call_special(bracket, object, "__index__", vec![index.clone()])? {
+            return Ok(value);
+        }
+        collection::get_index(object, index)
+            .map_err(|(kind, msg)| Error::RuntimeError(bracket.clone(), kind, msg))
+    }
+
+    // `object[index] = value`, `__setindex__(index, value)` on an instance.
+    pub(super) fn set_index(
+        &mut self,
+        bracket: &Token,
+        object: &Value,
+        index: &Value,
+        value: Value,
+    ) -> Result<(), Error> {
+        let args = vec![index.clone(), value.clone()];
+        if self
+            .call_special(bracket, object, "__setindex__", args)?
+            .is_some()
+        {
+            return Ok(());
+        }
+        // a new key adds an entry to the map.
+        if let Value::Map(map) = object {
+            if let Ok(None) = map.borrow().get(index) {
+                self.allocate(bracket, 2 * mem::size_of::<Value>())?;
+            }
+        }
+        collection::set_index(object, index, value)
+            .map_err(|(kind, msg)| Error::RuntimeError(bracket.clone(), kind, msg))
+    }
+
+    // how `print` and `str()` show a value: an instance with `__str__` shows what it returns,
+    // also inside a list or map.
+    // the caller counts the string it gets against the memory cap, the parts are not counted.
+    pub(super) fn stringify(&mut self, token: &Token, value: &Value) -> Result<String, Error> {
+        match value {
+            Value::Instance(_) => match self.call_special(token, value, "__str__", Vec::new())? {
+                Some(s) => Ok(s.to_string()),
+                None => Ok(value.to_string()),
+            },
+            Value::List(list) => {
+                let items = list.borrow().clone();
+                let items = collection::printing(Rc::as_ptr(list).cast(), || {
+                    let mut strings = Vec::new();
+                    for item in &items {
+                        strings.push(self.repr(token, item)?);
+                    }
+                    Ok(strings.join(", "))
+                });
+                Ok(format!(
+                    "[{}]",
+                    items.transpose()?.as_deref().unwrap_or("...")
+                ))
+            }
+            Value::Map(map) => {
+                let entries = map.borrow().entries().to_vec();
+                let entries = collection::printing(Rc::as_ptr(map).cast(), || {
+                    let mut strings = Vec::new();
+                    for (key, value) in &entries {
+                        strings.push(format!(
+                            "{}: {}",
+                            self.repr(token, key)?,
+                            self.repr(token, value)?
+                        ));
+                    }
+                    Ok(strings.join(", "))
+                });
+                Ok(format!(
+                    "{{{}}}",
+                    entries.transpose()?.as_deref().unwrap_or("...")
+                ))
+            }
+            _ => Ok(value.to_string()),
+        }
+    }
+
+    fn repr(&mut self, token: &Token, value: &Value) -> Result<String, Error> {
+        match value {
+            Value::String(_) => Ok(collection::repr(value)),
+            _ => self.stringify(token, value),
+        }
+    }
+}
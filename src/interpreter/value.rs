@@ -1,31 +1,256 @@
 use core::fmt;
 use std::{
     cell::RefCell,
-    ops::{Add, Div, Mul, Sub},
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    mem,
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Rem, Shl, Shr, Sub},
     rc::Rc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use super::{environment::Environment, Interpreter};
+use super::{
+    collection::{printing, repr, MapValue, Range},
+    environment::Environment,
+    fiber::{Channel, Fiber},
+    generator::Generator,
+    module::Module,
+    Interpreter,
+};
 use crate::{
-    ast::Stmt,
-    error::Error,
+    ast::{Param, Stmt},
+    bignum::{BigInt, Decimal},
+    error::{Error, ErrorKind},
     token::{Token, TokenType},
 };
 
 pub trait Callable {
-    fn call(&self, i: Interpreter, args: Vec<Value>) -> Result<Value, Error>;
-    fn arity(&self) -> usize; // return the number of arguments of function or operation expects.
+    // `Rc<Self>` lets a class hand itself to the instances it creates,
+    // `paren` is the closing parenthesis of the call, for errors.
+    fn call(
+        self: Rc<Self>,
+        i: Interpreter,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error>;
+    fn arity(&self) -> Arity; // return the number of arguments of function or operation expects.
     fn to_string(&self) -> String;
+    // a Lox function, a tail call to it runs in the caller's loop.
+    fn as_function(self: Rc<Self>) -> Option<Rc<FuncValue>> {
+        None
+    }
+}
+
+// how many arguments a callable takes: defaults make some optional, a rest parameter takes any number more.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>, // None with a rest parameter
+}
+
+impl Arity {
+    pub fn exact(n: usize) -> Arity {
+        Arity {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    pub fn range(min: usize, max: usize) -> Arity {
+        Arity {
+            min,
+            max: Some(max),
+        }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum Value {
     Nil,
     Bool(bool),
+    Integer(i64), // integer literals and results of integer arithmetic, overflow is a runtime error.
+    BigInt(BigInt), // `10n`
+    Decimal(Decimal), // `1.20d`
     Number(f64),
     String(String),
     Callable(Rc<dyn Callable>),
+    Class(Rc<ClassValue>),
+    Trait(Rc<TraitValue>),
+    Instance(Rc<InstanceValue>),
+    Error(Rc<ErrorValue>), // a runtime error caught by `catch`.
+    Module(Rc<Module>),    // `import "util.lox" as util;`
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<MapValue>>),
+    Range(Range),
+    Generator(Rc<Generator>), // what calling a function with a `yield` returns.
+    Fiber(Rc<Fiber>),         // `Fiber(fn)`
+    Channel(Rc<Channel>),     // `Channel()`
+}
+
+// scripts read the fields of a caught runtime error as `e.message`, `e.line` and `e.kind`.
+pub struct ErrorValue {
+    pub message: String,
+    pub line: u32,
+    pub kind: ErrorKind,
+}
+
+impl ErrorValue {
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match name {
+            "message" => Some(Value::String(self.message.clone())),
+            "line" => Some(Value::Integer(self.line as i64)),
+            "kind" => Some(Value::String(self.kind.to_string())),
+            _ => None,
+        }
+    }
+}
+
+// the operands of an arithmetic operator after promotion along the numeric tower
+//   integer -> bigint -> decimal -> float
+// both operands are converted to the higher kind of the two: `1 + 2n` is a bigint, `2n * 1.5d` a decimal.
+// a float is inexact, so anything mixed with a float becomes a float.
+enum Numbers {
+    Integers(i64, i64),
+    BigInts(BigInt, BigInt),
+    Decimals(Decimal, Decimal),
+    Floats(f64, f64),
+}
+
+impl Numbers {
+    fn from(l: &Value, r: &Value) -> Option<Numbers> {
+        fn rank(v: &Value) -> Option<u8> {
+            match v {
+                Value::Integer(_) => Some(0),
+                Value::BigInt(_) => Some(1),
+                Value::Decimal(_) => Some(2),
+                Value::Number(_) => Some(3),
+                _ => None,
+            }
+        }
+        fn to_bigint(v: &Value) -> BigInt {
+            match v {
+                Value::Integer(num) => BigInt::from_i64(*num),
+                Value::BigInt(num) => num.clone(),
+                _ => unreachable!(),
+            }
+        }
+        fn to_decimal(v: &Value) -> Decimal {
+            match v {
+                Value::Decimal(num) => num.clone(),
+                _ => Decimal::from_bigint(to_bigint(v)),
+            }
+        }
+        fn to_f64(v: &Value) -> f64 {
+            match v {
+                Value::Integer(num) => *num as f64,
+                Value::BigInt(num) => num.to_f64(),
+                Value::Decimal(num) => num.to_f64(),
+                Value::Number(num) => *num,
+                _ => unreachable!(),
+            }
+        }
+
+        match (l, r) {
+            (Value::Integer(l), Value::Integer(r)) => Some(Numbers::Integers(*l, *r)),
+            _ => match rank(l)?.max(rank(r)?) {
+                1 => Some(Numbers::BigInts(to_bigint(l), to_bigint(r))),
+                2 => Some(Numbers::Decimals(to_decimal(l), to_decimal(r))),
+                _ => Some(Numbers::Floats(to_f64(l), to_f64(r))),
+            },
+        }
+    }
+
+    fn cmp(&self) -> Option<Ordering> {
+        match self {
+            Numbers::Integers(a, b) => Some(a.cmp(b)),
+            Numbers::BigInts(a, b) => Some(a.cmp(b)),
+            Numbers::Decimals(a, b) => Some(a.cmp(b)),
+            Numbers::Floats(a, b) => a.partial_cmp(b),
+        }
+    }
+
+    // the order of two numbers, `None` for NaN, or an outer `None` when they aren't both numbers.
+    // a float and an exact number compare by their exact values: as floats, `2^53 + 1 == 2^53`.
+    fn order(l: &Value, r: &Value) -> Option<Option<Ordering>> {
+        match (l, r) {
+            (Value::Number(l), r) if Numbers::is_exact(r) => Some(Numbers::order_float(*l, r)),
+            (l, Value::Number(r)) if Numbers::is_exact(l) => {
+                Some(Numbers::order_float(*r, l).map(Ordering::reverse))
+            }
+            _ => Numbers::from(l, r).map(|numbers| numbers.cmp()),
+        }
+    }
+
+    fn is_exact(v: &Value) -> bool {
+        matches!(v, Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_))
+    }
+
+    fn order_float(float: f64, exact: &Value) -> Option<Ordering> {
+        // an integer this small converts to a float without rounding.
+        const EXACT_FLOAT: i64 = 1 << 53;
+        if float.is_nan() {
+            return None;
+        }
+        if float.is_infinite() {
+            return Some(match float > 0.0 {
+                true => Ordering::Greater,
+                false => Ordering::Less,
+            });
+        }
+        let exact = match exact {
+            Value::Integer(num) if num.abs() <= EXACT_FLOAT => {
+                return float.partial_cmp(&(*num as f64));
+            }
+            Value::Integer(num) => Decimal::from_bigint(BigInt::from_i64(*num)),
+            Value::BigInt(num) => Decimal::from_bigint(num.clone()),
+            Value::Decimal(num) => num.clone(),
+            _ => unreachable!(),
+        };
+        Some(Decimal::from_f64(float).cmp(&exact))
+    }
+
+    // the operands of an operator that only takes numbers.
+    fn of(l: &Value, r: &Value) -> Result<Numbers, OpError> {
+        Numbers::from(l, r).ok_or_else(|| type_error("Operands must be numbers."))
+    }
+
+    fn divisor_is_zero(&self) -> bool {
+        match self {
+            Numbers::Integers(_, r) => *r == 0,
+            Numbers::BigInts(_, r) => r.is_zero(),
+            Numbers::Decimals(_, r) => r.is_zero(),
+            Numbers::Floats(_, r) => *r == 0.0,
+        }
+    }
+}
+
+// the error of an operator: its kind and message, the interpreter adds the operator token.
+pub type OpError = (ErrorKind, String);
+
+fn overflow() -> OpError {
+    (ErrorKind::Arithmetic, "Integer overflow.".to_string())
+}
+
+pub fn type_error(msg: &str) -> OpError {
+    (ErrorKind::Type, msg.to_string())
+}
+
+fn arithmetic_error(msg: &str) -> OpError {
+    (ErrorKind::Arithmetic, msg.to_string())
 }
 
 impl Value {
@@ -51,40 +276,83 @@ impl Value {
         }
     }
     */
-    pub fn ge(&self, oth: &Self) -> Value {
-        match (self, oth) {
-            (Value::Number(a), Value::Number(b)) => Value::Bool(a >= b),
-            _ => Value::Bool(false),
+    // every binary operator on the built-in values, so they check their operands the same way.
+    // `+=` and `++` are additions, `-=` and `--` subtractions, and so on.
+    pub fn binary(op: &TokenType, l: Value, r: Value) -> Result<Value, OpError> {
+        match op {
+            TokenType::PLUS | TokenType::PlusEqual | TokenType::PlusPlus => l + r,
+            TokenType::MINUS | TokenType::MinusEqual | TokenType::MinusMinus => l - r,
+            TokenType::STAR | TokenType::StarEqual => l * r,
+            TokenType::SLASH | TokenType::SlashEqual => l / r,
+            TokenType::PERCENT | TokenType::PercentEqual => l % r,
+            TokenType::TildeSlash => l.int_div(&r),
+            TokenType::AMPERSAND => l & r,
+            TokenType::PIPE => l | r,
+            TokenType::CARET => l ^ r,
+            TokenType::LessLess => l << r,
+            TokenType::GreaterGreater => l >> r,
+            TokenType::GREATER => l.compare(&r, Ordering::is_gt),
+            TokenType::GreaterEqual => l.compare(&r, Ordering::is_ge),
+            TokenType::LESS => l.compare(&r, Ordering::is_lt),
+            TokenType::LessEqual => l.compare(&r, Ordering::is_le),
+            TokenType::EqualEqual => Ok(Value::Bool(l == r)),
+            TokenType::BangEqual => Ok(Value::Bool(l != r)),
+            _ => unreachable!("{} is not a binary operator", op),
         }
     }
 
-    pub fn gt(&self, oth: &Self) -> Value {
-        match (self, oth) {
-            (Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
-            _ => Value::Bool(false),
+    // numbers compare by value and strings lexicographically, by their unicode code points.
+    // a comparison with NaN is false.
+    fn compare(&self, oth: &Self, pred: fn(Ordering) -> bool) -> Result<Value, OpError> {
+        if let (Value::String(l), Value::String(r)) = (self, oth) {
+            return Ok(Value::Bool(pred(l.cmp(r))));
         }
+        let ord =
+            Numbers::order(self, oth).ok_or_else(|| type_error("Operands must be numbers."))?;
+        Ok(Value::Bool(ord.is_some_and(pred)))
     }
-    pub fn le(&self, oth: &Self) -> Value {
-        match (self, oth) {
-            (Value::Number(a), Value::Number(b)) => Value::Bool(a <= b),
-            _ => Value::Bool(false),
+
+    pub fn neg(&self) -> Result<Value, OpError> {
+        match self {
+            Value::Integer(num) => num.checked_neg().map(Value::Integer).ok_or_else(overflow),
+            Value::BigInt(num) => Ok(Value::BigInt(num.neg())),
+            Value::Decimal(num) => Ok(Value::Decimal(num.neg())),
+            Value::Number(num) => Ok(Value::Number(-num)),
+            _ => Err(type_error("Operand must be a number.")),
         }
     }
-    pub fn lt(&self, oth: &Self) -> Value {
-        match (self, oth) {
-            (Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
-            _ => Value::Bool(false),
+
+    // `~/` divides and truncates toward zero, so `a == (a ~/ b) * b + a % b`.
+    // it returns an integer for two integers and a float with no fractional part otherwise.
+    pub fn int_div(&self, oth: &Self) -> Result<Value, OpError> {
+        match Numbers::of(self, oth)? {
+            numbers if numbers.divisor_is_zero() => Err(arithmetic_error("Division by zero.")),
+            Numbers::Integers(l, r) => l.checked_div(r).map(Value::Integer).ok_or_else(overflow),
+            Numbers::BigInts(l, r) => Ok(Value::BigInt(l.div_rem(&r).0)),
+            Numbers::Decimals(l, r) => Ok(Value::Decimal(l.int_div(&r))),
+            Numbers::Floats(l, r) => Ok(Value::Number((l / r).trunc())),
+        }
+    }
+
+    // `~` flips every bit of an integer.
+    pub fn bit_not(&self) -> Result<Value, OpError> {
+        match self {
+            Value::Integer(num) => Ok(Value::Integer(!num)),
+            _ => Err(type_error("Operand must be an integer.")),
         }
     }
 }
 
 impl TryFrom<&TokenType> for Value {
     type Error = String;
-    fn try_from(t: &TokenType) -> Result<Value, Self::Error> {
+    fn try_from(t: &TokenType) -> Result<Value, String> {
         match t {
             TokenType::NIL => Ok(Value::Nil),
             TokenType::FALSE => Ok(Value::Bool(false)),
             TokenType::TRUE => Ok(Value::Bool(true)),
+            TokenType::INTEGER { literal } => Ok(Value::Integer(*literal)),
+            TokenType::BIGINT { literal } => Ok(Value::BigInt(literal.clone())),
+            TokenType::DECIMAL { literal } => Ok(Value::Decimal(literal.clone())),
             TokenType::NUMBER { literal } => Ok(Value::Number(literal.0)),
             TokenType::STRING { literal } => Ok(Value::String(literal.clone())),
             _ => Err("cast TokenType to Value error".to_string()),
@@ -93,56 +361,144 @@ impl TryFrom<&TokenType> for Value {
 }
 
 impl Sub for Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, OpError>;
     fn sub(self, rhs: Value) -> Self::Output {
-        if let (Value::Number(l), Value::Number(r)) = (self, rhs) {
-            return Ok(Value::Number(l - r));
+        match Numbers::of(&self, &rhs)? {
+            Numbers::Integers(l, r) => l.checked_sub(r).map(Value::Integer).ok_or_else(overflow),
+            Numbers::BigInts(l, r) => Ok(Value::BigInt(l.sub(&r))),
+            Numbers::Decimals(l, r) => Ok(Value::Decimal(l.sub(&r))),
+            Numbers::Floats(l, r) => Ok(Value::Number(l - r)),
         }
-        return Err("Value cannot sub".to_string());
     }
 }
 
 impl Add for Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, OpError>;
     fn add(self, rhs: Value) -> Self::Output {
-        match (self, rhs) {
-            (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
-            (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r))),
-            _ => Err("Value cannot add".to_string()),
+        if let (Value::String(l), Value::String(r)) = (&self, &rhs) {
+            return Ok(Value::String(format!("{}{}", l, r)));
+        }
+        match Numbers::from(&self, &rhs) {
+            Some(Numbers::Integers(l, r)) => {
+                l.checked_add(r).map(Value::Integer).ok_or_else(overflow)
+            }
+            Some(Numbers::BigInts(l, r)) => Ok(Value::BigInt(l.add(&r))),
+            Some(Numbers::Decimals(l, r)) => Ok(Value::Decimal(l.add(&r))),
+            Some(Numbers::Floats(l, r)) => Ok(Value::Number(l + r)),
+            None => Err(type_error("Operands must be two numbers or two strings.")),
         }
     }
 }
 
+// `/` is always a true division, even for two integers: `7 / 2` is `3.5`. use `~/` to get an integer.
+// two bigints divide exactly into a decimal: `7n / 2n` is `3.5`.
 impl Div for Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, OpError>;
     fn div(self, rhs: Value) -> Self::Output {
-        match (self, rhs) {
-            (Value::Number(l), Value::Number(r)) => match r {
-                0.0 => Err("divide by zero".to_string()),
-                _ => Ok(Value::Number(l / r)),
-            },
-            _ => Err("Value cannot div".to_string()),
+        match Numbers::of(&self, &rhs)? {
+            numbers if numbers.divisor_is_zero() => Err(arithmetic_error("Division by zero.")),
+            Numbers::Integers(l, r) => Ok(Value::Number(l as f64 / r as f64)),
+            Numbers::BigInts(l, r) => Ok(Value::Decimal(
+                Decimal::from_bigint(l).div(&Decimal::from_bigint(r)),
+            )),
+            Numbers::Decimals(l, r) => Ok(Value::Decimal(l.div(&r))),
+            Numbers::Floats(l, r) => Ok(Value::Number(l / r)),
         }
     }
 }
 
 impl Mul for Value {
-    type Output = Result<Value, String>;
+    type Output = Result<Value, OpError>;
     fn mul(self, rhs: Value) -> Self::Output {
-        if let (Value::Number(l), Value::Number(r)) = (self, rhs) {
-            return Ok(Value::Number(l * r));
+        match Numbers::of(&self, &rhs)? {
+            Numbers::Integers(l, r) => l.checked_mul(r).map(Value::Integer).ok_or_else(overflow),
+            Numbers::BigInts(l, r) => Ok(Value::BigInt(l.mul(&r))),
+            Numbers::Decimals(l, r) => Ok(Value::Decimal(l.mul(&r))),
+            Numbers::Floats(l, r) => Ok(Value::Number(l * r)),
+        }
+    }
+}
+
+// Lox's `%` truncates like C, Java and JavaScript: the result has the sign of the dividend,
+// so `-7 % 3` is `-1` and `7 % -3` is `1`. it is Rust's `%` on f64, which also works with fractions.
+impl Rem for Value {
+    type Output = Result<Value, OpError>;
+    fn rem(self, rhs: Value) -> Self::Output {
+        match Numbers::of(&self, &rhs)? {
+            numbers if numbers.divisor_is_zero() => Err(arithmetic_error("Modulo by zero.")),
+            Numbers::Integers(l, r) => l.checked_rem(r).map(Value::Integer).ok_or_else(overflow),
+            Numbers::BigInts(l, r) => Ok(Value::BigInt(l.div_rem(&r).1)),
+            Numbers::Decimals(l, r) => Ok(Value::Decimal(l.rem(&r))),
+            Numbers::Floats(l, r) => Ok(Value::Number(l % r)),
         }
-        Err("Value cannot mul".to_string())
     }
 }
 
+// the bitwise operators only work on integers.
+fn integers(l: &Value, r: &Value) -> Result<(i64, i64), OpError> {
+    match (l, r) {
+        (Value::Integer(l), Value::Integer(r)) => Ok((*l, *r)),
+        _ => Err(type_error("Operands must be integers.")),
+    }
+}
+
+// shifting by a negative amount or by 64 bits or more is an error.
+fn shift_amount(r: i64) -> Result<u32, OpError> {
+    match r {
+        0..=63 => Ok(r as u32),
+        _ => Err(arithmetic_error("Shift amount out of range.")),
+    }
+}
+
+impl BitAnd for Value {
+    type Output = Result<Value, OpError>;
+    fn bitand(self, rhs: Value) -> Self::Output {
+        let (l, r) = integers(&self, &rhs)?;
+        Ok(Value::Integer(l & r))
+    }
+}
+
+impl BitOr for Value {
+    type Output = Result<Value, OpError>;
+    fn bitor(self, rhs: Value) -> Self::Output {
+        let (l, r) = integers(&self, &rhs)?;
+        Ok(Value::Integer(l | r))
+    }
+}
+
+impl BitXor for Value {
+    type Output = Result<Value, OpError>;
+    fn bitxor(self, rhs: Value) -> Self::Output {
+        let (l, r) = integers(&self, &rhs)?;
+        Ok(Value::Integer(l ^ r))
+    }
+}
+
+impl Shl for Value {
+    type Output = Result<Value, OpError>;
+    fn shl(self, rhs: Value) -> Self::Output {
+        let (l, r) = integers(&self, &rhs)?;
+        Ok(Value::Integer(l << shift_amount(r)?))
+    }
+}
+
+// `>>` is an arithmetic shift, it keeps the sign.
+impl Shr for Value {
+    type Output = Result<Value, OpError>;
+    fn shr(self, rhs: Value) -> Self::Output {
+        let (l, r) = integers(&self, &rhs)?;
+        Ok(Value::Integer(l >> shift_amount(r)?))
+    }
+}
+
+// numbers of different kinds are equal when their values are: `1 == 1.0`, `1.20d == 1.2d`.
 impl PartialEq for Value {
     fn eq(&self, oth: &Self) -> bool {
+        if let Some(ord) = Numbers::order(self, oth) {
+            return ord == Some(Ordering::Equal);
+        }
         match &self {
-            Value::Nil => match oth {
-                Value::Nil => true,
-                _ => false,
-            },
+            Value::Nil => matches!(oth, Value::Nil),
             Value::String(s) => {
                 if let Value::String(oth) = oth {
                     return s.eq(oth);
@@ -155,9 +511,76 @@ impl PartialEq for Value {
                 }
                 false
             }
-            Value::Number(num) => {
-                if let Value::Number(oth) = oth {
-                    return num.eq(oth);
+            Value::Error(e) => {
+                if let Value::Error(oth) = oth {
+                    return Rc::ptr_eq(e, oth);
+                }
+                false
+            }
+            Value::Module(m) => {
+                if let Value::Module(oth) = oth {
+                    return Rc::ptr_eq(m, oth);
+                }
+                false
+            }
+            Value::List(l) => {
+                if let Value::List(oth) = oth {
+                    return Rc::ptr_eq(l, oth);
+                }
+                false
+            }
+            Value::Map(m) => {
+                if let Value::Map(oth) = oth {
+                    return Rc::ptr_eq(m, oth);
+                }
+                false
+            }
+            Value::Range(r) => {
+                if let Value::Range(oth) = oth {
+                    return r == oth;
+                }
+                false
+            }
+            Value::Class(c) => {
+                if let Value::Class(oth) = oth {
+                    return Rc::ptr_eq(c, oth);
+                }
+                false
+            }
+            // functions have identity equality, every `foo.method` is a new bound method.
+            Value::Callable(c) => {
+                if let Value::Callable(oth) = oth {
+                    return std::ptr::addr_eq(Rc::as_ptr(c), Rc::as_ptr(oth));
+                }
+                false
+            }
+            Value::Trait(t) => {
+                if let Value::Trait(oth) = oth {
+                    return Rc::ptr_eq(t, oth);
+                }
+                false
+            }
+            Value::Instance(inst) => {
+                if let Value::Instance(oth) = oth {
+                    return Rc::ptr_eq(inst, oth);
+                }
+                false
+            }
+            Value::Generator(g) => {
+                if let Value::Generator(oth) = oth {
+                    return Rc::ptr_eq(g, oth);
+                }
+                false
+            }
+            Value::Fiber(fiber) => {
+                if let Value::Fiber(oth) = oth {
+                    return Rc::ptr_eq(fiber, oth);
+                }
+                false
+            }
+            Value::Channel(channel) => {
+                if let Value::Channel(oth) = oth {
+                    return Rc::ptr_eq(channel, oth);
                 }
                 false
             }
@@ -171,52 +594,171 @@ impl fmt::Display for Value {
         match self {
             Value::Nil => write!(f, "nil"),
             Value::Bool(b) => write!(f, "{}", b),
+            Value::Integer(num) => write!(f, "{}", num),
+            Value::BigInt(num) => write!(f, "{}", num),
+            Value::Decimal(num) => write!(f, "{}", num),
             Value::Number(num) => write!(f, "{}", num),
             Value::String(s) => write!(f, "{}", s),
             Value::Callable(c) => write!(f, "{}", c.to_string()),
+            Value::Class(c) => write!(f, "{}", c.name),
+            Value::Trait(t) => write!(f, "{}", t.name),
+            Value::Instance(inst) => write!(f, "{} instance", inst.class.name),
+            Value::Error(e) => write!(f, "{}", e.message),
+            Value::Module(m) => write!(f, "<module {}>", m.name),
+            Value::Generator(g) => write!(f, "<generator {}>", g.name()),
+            Value::Fiber(fiber) => write!(f, "<fiber {}>", fiber.name()),
+            Value::Channel(_) => write!(f, "<channel>"),
+            Value::List(list) => {
+                let items = printing(Rc::as_ptr(list).cast(), || {
+                    let items: Vec<String> = list.borrow().iter().map(repr).collect();
+                    items.join(", ")
+                });
+                write!(f, "[{}]", items.as_deref().unwrap_or("..."))
+            }
+            Value::Map(map) => {
+                let entries = printing(Rc::as_ptr(map).cast(), || {
+                    let entries: Vec<String> = map
+                        .borrow()
+                        .entries()
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", repr(key), repr(value)))
+                        .collect();
+                    entries.join(", ")
+                });
+                write!(f, "{{{}}}", entries.as_deref().unwrap_or("..."))
+            }
+            Value::Range(r) => write!(f, "{}", r),
         }
     }
 }
 
 pub struct FuncValue {
     name: Token,
-    params: Vec<Token>,
-    body: Vec<Stmt>,                   // Stmt::Block
+    params: Vec<Param>,
+    body: Rc<Vec<Stmt>>, // Stmt::Block, shared by the bound copies of a method.
     closure: Rc<RefCell<Environment>>, // the env when the function is declared, not when it's called.
+    module: Rc<Module>, // the file declaring the function, its globals stay visible when another file calls it.
+    is_initializer: bool, // `init` returns the instance, whatever its body returns.
+    is_generator: bool, // a body with a `yield` runs when the returned generator is iterated.
 }
 
 impl FuncValue {
     pub fn from(
         name: Token,
-        params: Vec<Token>,
+        params: Vec<Param>,
         body: Vec<Stmt>,
         closure: Rc<RefCell<Environment>>,
+        module: Rc<Module>,
+        is_initializer: bool,
     ) -> FuncValue {
         FuncValue {
             name,
             params,
-            body,
+            is_generator: body.iter().any(Stmt::contains_yield),
+            body: Rc::new(body),
             closure,
+            module,
+            is_initializer,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name.lexeme
+    }
+
+    // a new env for a call, with the parameters bound to the arguments.
+    fn bind_args(&self, i: &mut Interpreter, paren: &Token, args: Vec<Value>) -> Result<(), Error> {
+        i.env = Rc::new(RefCell::new(Environment::from(&self.closure)));
+        i.module = self.module.clone();
+        let mut args = args.into_iter();
+        for param in &self.params {
+            let value = if param.rest {
+                let rest: Vec<Value> = args.by_ref().collect();
+                i.allocate(paren, rest.len() * mem::size_of::<Value>())?;
+                Value::List(Rc::new(RefCell::new(rest)))
+            } else if let Some(arg) = args.next() {
+                arg
+            } else if let Some(default) = &param.default {
+                // evaluated in the function's env, after the parameters before it are bound.
+                i.eval(default)?
+            } else {
+                Value::Nil
+            };
+            i.env.borrow_mut().define(param.name.lexeme.clone(), value);
+        }
+        Ok(())
+    }
+
+    // a method bound to an instance: `this` lives in a new env between the method's closure and its body.
+    pub fn bind(&self, instance: Rc<InstanceValue>) -> FuncValue {
+        let mut env = Environment::from(&self.closure);
+        env.define("this".to_string(), Value::Instance(instance));
+        FuncValue {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: self.body.clone(),
+            closure: Rc::new(RefCell::new(env)),
+            module: self.module.clone(),
+            is_initializer: self.is_initializer,
+            is_generator: self.is_generator,
         }
     }
 }
 
 impl Callable for FuncValue {
-    fn call(&self, mut i: Interpreter, args: Vec<Value>) -> Result<Value, Error> {
-        let previous = i.env.clone();
-        i.env = Rc::new(RefCell::new(Environment::from(&self.closure)));
-        for idx in 0..self.params.len() {
-            i.env
-                .borrow_mut()
-                .define(self.params[idx].lexeme.clone(), args[idx].clone());
+    fn call(
+        self: Rc<Self>,
+        mut i: Interpreter,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let (mut func, mut args) = (self, args);
+        // a trampoline: `return g(x);` leaves the call to this loop, so tail recursion,
+        // even mutual, runs in one Rust frame.
+        loop {
+            func.bind_args(&mut i, paren, args)?;
+            if func.is_generator {
+                let generator = Generator::new(
+                    func.name.lexeme.clone(),
+                    func.body.clone(),
+                    i.env.clone(),
+                    func.module.clone(),
+                );
+                return Ok(Value::Generator(Rc::new(generator)));
+            }
+            let res = i.execute_stmts(&func.body)?;
+            if let Some(tail) = i.tail_call.take() {
+                match tail.callee.clone().as_function() {
+                    Some(next) => {
+                        (func, args) = (next, tail.args);
+                        continue;
+                    }
+                    // a native function or a class, it doesn't recurse through this frame.
+                    None => return tail.callee.call(i, &tail.paren, tail.args),
+                }
+            }
+            if func.is_initializer {
+                return func.closure.borrow().get_at(0, &"this".to_string());
+            }
+            // Lox 定义一个函数没有返回值时，默认返回 nil.
+            return Ok(res.0);
         }
-        let res = i.execute_stmts(&self.body)?;
-        i.env = previous;
-        // Lox 定义一个函数没有返回值时，默认返回 nil.
-        Ok(res.0)
     }
-    fn arity(&self) -> usize {
-        self.params.len()
+    fn as_function(self: Rc<Self>) -> Option<Rc<FuncValue>> {
+        Some(self)
+    }
+    fn arity(&self) -> Arity {
+        let required = self
+            .params
+            .iter()
+            .filter(|p| p.default.is_none() && !p.rest);
+        Arity {
+            min: required.count(),
+            max: match self.params.last() {
+                Some(param) if param.rest => None,
+                _ => Some(self.params.len()),
+            },
+        }
     }
     fn to_string(&self) -> String {
         format!("<fn {}>", self.name.lexeme)
@@ -226,7 +768,12 @@ impl Callable for FuncValue {
 pub struct Clock {}
 
 impl Callable for Clock {
-    fn call(&self, _i: Interpreter, _args: Vec<Value>) -> Result<Value, Error> {
+    fn call(
+        self: Rc<Self>,
+        _i: Interpreter,
+        _paren: &Token,
+        _args: Vec<Value>,
+    ) -> Result<Value, Error> {
         Ok(Value::Number(
             SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -234,10 +781,173 @@ impl Callable for Clock {
                 .as_secs_f64(),
         ))
     }
-    fn arity(&self) -> usize {
-        0
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
     }
     fn to_string(&self) -> String {
         String::from("<native fn>")
     }
 }
+
+pub struct ClassValue {
+    pub name: String,
+    super_class: Option<Rc<ClassValue>>,
+    methods: HashMap<String, Rc<FuncValue>>,
+    getters: HashMap<String, Rc<FuncValue>>,
+    statics: RefCell<HashMap<String, Value>>, // the static methods and fields, read as `Class.name`.
+    fields: HashSet<String>, // the static fields declared, the only ones assignable.
+}
+
+impl ClassValue {
+    pub fn new(
+        name: String,
+        super_class: Option<Rc<ClassValue>>,
+        methods: HashMap<String, Rc<FuncValue>>,
+        getters: HashMap<String, Rc<FuncValue>>,
+        statics: HashMap<String, Value>,
+        fields: HashSet<String>,
+    ) -> Self {
+        ClassValue {
+            name,
+            super_class,
+            methods,
+            getters,
+            statics: RefCell::new(statics),
+            fields,
+        }
+    }
+
+    // a static member of the class, or else of the nearest superclass having it.
+    pub fn get(&self, name: &Token) -> Result<Value, Error> {
+        if let Some(value) = self.statics.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        match &self.super_class {
+            Some(super_class) => super_class.get(name),
+            None => Err(Error::RuntimeError(
+                name.clone(),
+                ErrorKind::Name,
+                format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    // assign a static field of the class, or of the nearest superclass declaring it.
+    pub fn set(&self, name: &Token, value: Value) -> Result<(), Error> {
+        if self.fields.contains(&name.lexeme) {
+            self.statics.borrow_mut().insert(name.lexeme.clone(), value);
+            return Ok(());
+        }
+        match &self.super_class {
+            Some(super_class) => super_class.set(name, value),
+            None => Err(Error::RuntimeError(
+                name.clone(),
+                ErrorKind::Type,
+                "Only instances have fields.".to_string(),
+            )),
+        }
+    }
+
+    pub fn is_subclass_of(&self, class: &Rc<ClassValue>) -> bool {
+        std::ptr::eq(self, Rc::as_ptr(class))
+            || self
+                .super_class
+                .as_ref()
+                .is_some_and(|super_class| super_class.is_subclass_of(class))
+    }
+
+    // a method of the class, or else the nearest one up the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<Rc<FuncValue>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.super_class.as_ref()?.find_method(name),
+        }
+    }
+
+    fn find_getter(&self, name: &str) -> Option<Rc<FuncValue>> {
+        match self.getters.get(name) {
+            Some(getter) => Some(getter.clone()),
+            None => self.super_class.as_ref()?.find_getter(name),
+        }
+    }
+}
+
+// calling a class creates an instance and runs `init` on it, if there is one.
+impl Callable for ClassValue {
+    fn call(
+        self: Rc<Self>,
+        i: Interpreter,
+        paren: &Token,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        i.allocate(paren, mem::size_of::<InstanceValue>())?;
+        let instance = Rc::new(InstanceValue {
+            class: self.clone(),
+            fields: RefCell::new(HashMap::new()),
+        });
+        if let Some(init) = self.find_method("init") {
+            Rc::new(init.bind(instance.clone())).call(i, paren, args)?;
+        }
+        Ok(Value::Instance(instance))
+    }
+    fn arity(&self) -> Arity {
+        self.find_method("init")
+            .map_or(Arity::exact(0), |init| init.arity())
+    }
+    fn to_string(&self) -> String {
+        self.name.clone()
+    }
+}
+
+// the methods of a trait, copied into every class declared `with` it.
+pub struct TraitValue {
+    pub name: String,
+    pub methods: HashMap<String, Rc<FuncValue>>,
+}
+
+pub struct InstanceValue {
+    class: Rc<ClassValue>,
+    fields: RefCell<HashMap<String, Value>>,
+}
+
+impl InstanceValue {
+    // fields shadow methods.
+    pub fn get(self: &Rc<Self>, name: &Token) -> Result<Value, Error> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        if let Some(method) = self.class.find_method(&name.lexeme) {
+            return Ok(Value::Callable(Rc::new(method.bind(self.clone()))));
+        }
+        Err(Error::RuntimeError(
+            name.clone(),
+            ErrorKind::Name,
+            format!("Undefined property '{}'.", name.lexeme),
+        ))
+    }
+
+    // a method of the instance's class bound to it, fields don't shadow it.
+    pub fn method(self: &Rc<Self>, name: &str) -> Option<FuncValue> {
+        Some(self.class.find_method(name)?.bind(self.clone()))
+    }
+
+    // the getter bound to the instance, unless a field of the same name shadows it.
+    pub fn getter(self: &Rc<Self>, name: &str) -> Option<FuncValue> {
+        if self.fields.borrow().contains_key(name) {
+            return None;
+        }
+        Some(self.class.find_getter(name)?.bind(self.clone()))
+    }
+
+    pub fn is_instance_of(&self, class: &Rc<ClassValue>) -> bool {
+        self.class.is_subclass_of(class)
+    }
+
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    pub fn set(&self, name: &Token, value: Value) {
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+    }
+}
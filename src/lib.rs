@@ -1,31 +1,174 @@
+pub mod ast;
 pub mod interpreter;
 pub mod scanner;
 pub mod token;
 
-mod ast;
+mod bignum;
+mod checker;
 mod error;
 mod parser;
 mod resolver;
 
+use std::{
+    panic,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
 use parser::Parser;
 
+// how to run a script.
+#[derive(Clone)]
+pub struct Config {
+    // check the type annotations first, a type error stops the file from running.
+    pub check: bool,
+    // the deepest a call can nest, a deeper one is a "Stack overflow." runtime error.
+    // the script runs on a thread with a stack of `STACK_PER_CALL` bytes per level and some more
+    // to parse it:
+    // about 143MB for the default 1000 in a debug build, 20MB in a release one.
+    // a depth whose stack doesn't fit in `MAX_STACK` is an error, the script doesn't run.
+    pub max_depth: usize,
+    // the statements and expressions the script may evaluate, `None` for no limit.
+    pub fuel: Option<u64>,
+    // how long the script may run.
+    pub timeout: Option<Duration>,
+    // set it from another thread to stop the script.
+    pub interrupt: Option<Arc<AtomicBool>>,
+    // the bytes the script may allocate over the whole run, past it is an "Out of memory." runtime
+    // error. it's a budget, not a cap on the live memory: what the script frees still counts.
+    pub max_memory: Option<usize>,
+    // counts what the script allocates, keep a clone to read it.
+    pub allocations: Allocations,
+}
+
+// the approximate bytes a script has allocated for its strings, big numbers, lists, maps, instances
+// and closures.
+// it only grows, what the script frees isn't taken off.
+// the clones share the count, so the host can read it while the script runs or after.
+#[derive(Clone, Default)]
+pub struct Allocations(Arc<AtomicUsize>);
+
+impl Allocations {
+    pub fn allocated(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    // add `bytes` to the count, returns the new total.
+    fn add(&self, bytes: usize) -> usize {
+        self.0.fetch_add(bytes, Ordering::Relaxed) + bytes
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            check: false,
+            max_depth: 1000,
+            fuel: None,
+            timeout: None,
+            interrupt: None,
+            max_memory: None,
+            allocations: Allocations::default(),
+        }
+    }
+}
+
+// the Rust stack one level of Lox calls may take. a call to a function whose body is a single
+// `return` measures about 82KB in a debug build and 7KB in a release one, this leaves room for
+// some more statements and expressions nested in the body.
+// a body nested deeper than that runs out of stack before `max_depth`, the interpreter checks the
+// stack left before each call and expression so it's still a "Stack overflow." runtime error.
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) {
+    128 * 1024
+} else {
+    16 * 1024
+};
+
+// the Rust stack the parser, the resolver and the checker take at the deepest nesting the parser
+// allows, about 72KB per level in a debug build and 14KB in a release one.
+const STACK_TO_PARSE: usize = if cfg!(debug_assertions) {
+    16 * 1024 * 1024
+} else {
+    4 * 1024 * 1024
+};
+
+// the biggest stack the interpreter thread asks for.
+const MAX_STACK: usize = 1024 * 1024 * 1024;
+
+// the stack the interpreter stops at, a Lox call or expression past it has no room left.
+const STACK_RESERVE: usize = 8 * STACK_PER_CALL;
+
+// the stack of a thread running Lox calls `max_depth` deep, `None` past `MAX_STACK`.
+fn stack_size(max_depth: usize) -> Option<usize> {
+    max_depth
+        .checked_add(16)
+        .and_then(|levels| levels.checked_mul(STACK_PER_CALL))
+        .and_then(|stack_size| stack_size.checked_add(STACK_TO_PARSE))
+        .filter(|&stack_size| stack_size <= MAX_STACK)
+}
+
+// run source typed at the prompt, imports are relative to the working directory.
 pub fn exec(src: String) {
-    let tokens = match scanner::scan_tokens(src) {
-        None => return,
-        Some(tokens) => tokens,
+    exec_file(src, Path::new(""), &Config::default());
+}
+
+// run the source of a file, imports are relative to its directory.
+// the interpreter recurses on the Rust stack, so it runs on a thread whose stack fits `max_depth`
+// calls: a runaway recursion is a Lox error instead of crashing the host.
+pub fn exec_file(src: String, path: &Path, config: &Config) {
+    let Some(stack_size) = stack_size(config.max_depth) else {
+        eprintln!(
+            "Error: A max depth of {} needs too big a stack, it can be at most {}.",
+            config.max_depth,
+            (MAX_STACK - STACK_TO_PARSE) / STACK_PER_CALL - 16
+        );
+        return;
     };
+    thread::scope(|scope| {
+        let interpreter = thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, || run(src, path, config));
+        match interpreter {
+            Ok(interpreter) => {
+                if let Err(e) = interpreter.join() {
+                    panic::resume_unwind(e);
+                }
+            }
+            Err(e) => eprintln!("Error: Failed to start the interpreter: {}.", e),
+        }
+    });
+}
 
-    let stmts = match Parser::new(tokens).parse() {
-        Ok(stmts) => stmts,
-        Err(_) => return, // the errors have been print in the `parser::parse()`
+fn run(src: String, path: &Path, config: &Config) {
+    let stmts = match parse(src) {
+        Some(stmts) => stmts,
+        None => return,
     };
 
-    let locations = match resolver::resolve_variable(&stmts) {
-        Some(locations) => locations,
+    let resolution = match resolver::resolve_variable(&stmts) {
+        Some(resolution) => resolution,
         None => return,
     };
 
-    interpreter::interpret(stmts, locations);
+    if config.check && !checker::check(&stmts) {
+        return;
+    }
+
+    interpreter::interpret(stmts, resolution, path, config);
+}
+
+// scan and parse the source without running it, e.g. for tools that read the doc comments
+// attached to `Stmt::Function`, `Stmt::Class` and `Stmt::Var`.
+pub fn parse(src: String) -> Option<Vec<ast::Stmt>> {
+    let tokens = scanner::scan_tokens(src)?;
+
+    // the errors have been print in the `parser::parse()`
+    Parser::new(tokens).parse().ok()
 }
 
 #[cfg(test)]
@@ -37,4 +180,74 @@ mod tests {
         let src = "print 1 + 100;".to_string();
         exec(src);
     }
+
+    // the tests return instead of hanging.
+    #[test]
+    fn timeout() {
+        let config = Config {
+            timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        exec_file("while (true) {}".to_string(), Path::new(""), &config);
+    }
+
+    #[test]
+    fn interrupt() {
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let config = Config {
+            interrupt: Some(interrupt.clone()),
+            ..Default::default()
+        };
+        let host = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            interrupt.store(true, Ordering::Relaxed);
+        });
+        exec_file("while (true) {}".to_string(), Path::new(""), &config);
+        host.join().unwrap();
+    }
+
+    // a depth whose stack doesn't fit is reported, instead of overflowing the size.
+    #[test]
+    fn max_depth() {
+        let config = Config {
+            max_depth: usize::MAX,
+            ..Default::default()
+        };
+        exec_file("print 1;".to_string(), Path::new(""), &config);
+    }
+
+    #[test]
+    fn allocations() {
+        let config = Config::default();
+        let allocations = config.allocations.clone();
+        exec_file(
+            "var s = \"ab\" + \"cd\"; s = s + s;".to_string(),
+            Path::new(""),
+            &config,
+        );
+        assert_eq!(allocations.allocated(), 12);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let src = "/// Adds two numbers.\n/// Returns the sum.\nfun add(a, b) { return a + b; }\n\
+                   // not a doc comment.\nvar x = 1;\n/// The answer.\nvar y = /* inline */ 42;"
+            .to_string();
+        let stmts = parse(src).unwrap();
+        let docs: Vec<Option<String>> = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                ast::Stmt::Function { doc, .. } | ast::Stmt::Var { doc, .. } => doc.clone(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            docs,
+            vec![
+                Some("Adds two numbers.\nReturns the sum.".to_string()),
+                None,
+                Some("The answer.".to_string()),
+            ]
+        );
+    }
 }
@@ -1,23 +1,76 @@
 use std::env;
 use std::fs;
 use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_none() {
+        return run_prompt();
+    }
+    let mut config = lox::Config::default();
+    // the options come before the file:
+    // `--check` checks the type annotations before running the file,
+    // `--fuel n` and `--timeout ms` stop a script that runs too long,
+    // `--max-memory bytes` one that allocates more than that over the whole run, freed or not,
+    // `--max-depth n` sets how deep calls can nest.
+    while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+        match option.as_str() {
+            "--check" => config.check = true,
+            "--fuel" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => config.fuel = Some(n),
+                None => return usage(),
+            },
+            "--timeout" => match args.next().and_then(|ms| ms.parse().ok()) {
+                Some(ms) => config.timeout = Some(Duration::from_millis(ms)),
+                None => return usage(),
+            },
+            "--max-memory" => match args.next().and_then(|bytes| bytes.parse().ok()) {
+                Some(bytes) => config.max_memory = Some(bytes),
+                None => return usage(),
+            },
+            "--max-depth" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => config.max_depth = n,
+                None => return usage(),
+            },
+            _ => return usage(),
+        }
+    }
+    let args: Vec<String> = args.collect();
     match args.len() {
-        1 => run_prompt(),
-        2 => run_file(&args[1]),
-        _ => println!("Usage: lox [filename]"),
+        1 => run_file(&args[0], &config),
+        _ => usage(),
     }
 }
 
-fn run_file(file: &String) {
-    let src = fs::read_to_string(file).unwrap_or_else(|_| {
+fn usage() {
+    println!(
+        "Usage: lox [--check] [--fuel steps] [--timeout ms] [--max-memory bytes] \
+         [--max-depth n] [filename]\n  \
+         --max-memory bytes  the bytes the script may allocate over the whole run, freed or not"
+    );
+}
+
+fn run_file(file: &String, config: &lox::Config) {
+    let bytes = fs::read(file).unwrap_or_else(|_| {
         println!("Failed to read file {}", file);
-        String::new()
+        Vec::new()
     });
 
-    run(src);
+    // the scanner works on chars, so reject a file that is not valid UTF-8 up front,
+    // instead of silently replacing the bad bytes.
+    let src = match String::from_utf8(bytes) {
+        Ok(src) => src,
+        Err(e) => {
+            let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+            let line = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+            eprintln!("[line {}] Error: Invalid UTF-8 in file {}.", line, file);
+            return;
+        }
+    };
+
+    lox::exec_file(src, Path::new(file), config);
 }
 
 fn run_prompt() {
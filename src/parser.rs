@@ -1,9 +1,16 @@
+use std::collections::HashMap;
+
 use crate::{
-    ast::{Expr, Stmt},
+    ast::{Destructure, Expr, MatchCase, Param, Pattern, Stmt},
+    bignum::{BigInt, Decimal},
     error::Error,
     token::{self, Token, TokenType, F64},
 };
 
+// how deep statements and expressions can nest, a deeper one is a parse error: the parser and the
+// passes after it recurse on the Rust stack.
+pub(crate) const MAX_NESTING: usize = 200;
+
 // the Lox language defines the syntactic grammar (https://craftinginterpreters.com/appendix-i.html).
 // the Parser uses the syntactic grammar to parse the linear sequence of tokens into a nested syntax tree.
 // the parser uses recursive descent algo.
@@ -11,11 +18,35 @@ use crate::{
 pub struct Parser {
     tokens: Vec<Token>,
     cur: usize,
+    docs: HashMap<usize, String>, // doc comments, keyed by the index of the token that follows them.
+    nesting: usize, // the statements and expressions the one being parsed is nested in.
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens, cur: 0 }
+        // doc comments can appear anywhere, so take them out of the token stream here and only look
+        // them up when parsing a declaration. consecutive `///` lines form one doc comment.
+        let mut docs: HashMap<usize, String> = HashMap::new();
+        let mut rest = Vec::new();
+        for token in tokens {
+            match token.t {
+                TokenType::DocComment { literal } => {
+                    docs.entry(rest.len())
+                        .and_modify(|doc| {
+                            doc.push('\n');
+                            doc.push_str(&literal);
+                        })
+                        .or_insert(literal);
+                }
+                _ => rest.push(token),
+            }
+        }
+        Parser {
+            tokens: rest,
+            cur: 0,
+            docs,
+            nesting: 0,
+        }
     }
 
     // program -> declaration* EOF ;
@@ -39,25 +70,60 @@ impl Parser {
         }
     }
 
-    // declaration -> classDecl | funDecl | varDecl | statement ;
+    // declaration -> classDecl | traitDecl | funDecl | varDecl | importDecl | statement ;
     // Lox 定义有些地方不能是 classDecl / funDecl / varDecl, 其他 stmt 都可以, 所以把这3个从 stmt 中提出来.
     fn declaration(&mut self) -> Result<Stmt, Error> {
         let token = self.peek();
         match &token.t {
-            TokenType::VAR => self.var_decl(),
-            TokenType::FUN => self.func_decl("function"),
-            TokenType::CLASS => todo!(),
+            TokenType::VAR | TokenType::CONST => self.var_decl(),
+            TokenType::FUN => self.func_decl(),
+            TokenType::IMPORT => self.import_decl(),
+            TokenType::CLASS => self.class_decl(),
+            TokenType::TRAIT => self.trait_decl(),
             _ => self.statement(),
         }
     }
 
-    // varDecl -> "var" IDENTIFIER ( "=" expression )? ";" ;
+    // the doc comment written right before the current token, if any.
+    fn doc(&self) -> Option<String> {
+        self.docs.get(&self.cur).cloned()
+    }
+
+    // varDecl -> "var" IDENTIFIER ( ":" type )? ( "=" expression )? ";"
+    //          | "const" IDENTIFIER ( ":" type )? "=" expression ";"
+    //          | ( "var" | "const" ) destructure "=" expression ";" ;
     fn var_decl(&mut self) -> Result<Stmt, Error> {
-        self.consume(TokenType::VAR, "Expect keyword 'var'")?;
+        let doc = self.doc();
+        let constant = self.r#match(&[TokenType::CONST]);
+        if !constant {
+            self.consume(TokenType::VAR, "Expect keyword 'var'")?;
+        }
+
+        if matches!(self.peek().t, TokenType::LeftBracket | TokenType::LeftBrace) {
+            let target = self.destructure()?;
+            self.consume(TokenType::EQUAL, "Expect '=' after destructuring pattern.")?;
+            let initializer = self.expression()?;
+            self.consume(
+                TokenType::SEMICOLON,
+                "Expect ';' after variable declaration.",
+            )?;
+            return Ok(Stmt::Destructure {
+                target,
+                initializer,
+                constant,
+            });
+        }
 
         let name = self.consume(TokenType::IDENTIFIER, "Expect variable name")?;
+        let annotation = self.annotation()?;
         let initializer = match self.r#match(&[TokenType::EQUAL]) {
             true => Some(self.expression()?),
+            false if constant => {
+                return Err(Error::ParseError(
+                    self.peek().clone(),
+                    "Constant must be initialized.".to_string(),
+                ))
+            }
             false => None,
         };
         self.consume(
@@ -65,64 +131,324 @@ impl Parser {
             "Expect ';' after variable declaration.",
         )?;
 
-        Ok(Stmt::Var { name, initializer })
+        Ok(Stmt::Var {
+            name,
+            annotation,
+            initializer,
+            constant,
+            doc,
+        })
+    }
+
+    // destructure -> "[" ( element ( "," element )* )? "]" | "{" ( IDENTIFIER ( "," IDENTIFIER )* )? "}" ;
+    // element -> "..."? IDENTIFIER ;
+    fn destructure(&mut self) -> Result<Destructure, Error> {
+        if self.r#match(&[TokenType::LeftBrace]) {
+            let brace = self.previous();
+            let mut names = Vec::new();
+            if self.peek().t != TokenType::RightBrace {
+                loop {
+                    names.push(self.consume(TokenType::IDENTIFIER, "Expect field name.")?);
+                    if !self.r#match(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after field names.")?;
+            return Ok(Destructure::Fields { brace, names });
+        }
+
+        let bracket = self.consume(TokenType::LeftBracket, "Expect '['.")?;
+        let (mut names, mut rest) = (Vec::new(), None);
+        if self.peek().t != TokenType::RightBracket {
+            loop {
+                if rest.is_some() {
+                    return Err(Error::ParseError(
+                        self.peek().clone(),
+                        "Rest element must be the last element.".to_string(),
+                    ));
+                }
+                let dots = self.r#match(&[TokenType::DotDotDot]);
+                let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
+                match dots {
+                    true => rest = Some(name),
+                    false => names.push(name),
+                }
+                if !self.r#match(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after variable names.")?;
+        Ok(Destructure::List {
+            bracket,
+            names,
+            rest,
+        })
+    }
+
+    // the optional `: type` after a variable, a parameter or a function's parameters.
+    // type -> IDENTIFIER | "nil" | "fun" ;
+    fn annotation(&mut self) -> Result<Option<Token>, Error> {
+        if !self.r#match(&[TokenType::COLON]) {
+            return Ok(None);
+        }
+        match self.peek().t {
+            TokenType::IDENTIFIER | TokenType::NIL | TokenType::FUN => Ok(Some(self.advance())),
+            _ => Err(Error::ParseError(
+                self.peek().clone(),
+                "Expect type name.".to_string(),
+            )),
+        }
+    }
+
+    // importDecl -> "import" STRING "as" IDENTIFIER ";" ;
+    // `as` is not a keyword, it only means something right after the module path.
+    fn import_decl(&mut self) -> Result<Stmt, Error> {
+        let keyword = self.consume(TokenType::IMPORT, "Expect keyword 'import'.")?;
+        let path = match &self.peek().t {
+            TokenType::STRING { literal } => literal.clone(),
+            _ => {
+                return Err(Error::ParseError(
+                    self.peek().clone(),
+                    "Expect module path after 'import'.".to_string(),
+                ))
+            }
+        };
+        self.advance();
+
+        let r#as = self.peek();
+        if r#as.t != TokenType::IDENTIFIER || r#as.lexeme != "as" {
+            return Err(Error::ParseError(
+                r#as.clone(),
+                "Expect 'as' after module path.".to_string(),
+            ));
+        }
+        self.advance();
+
+        let name = self.consume(TokenType::IDENTIFIER, "Expect module name after 'as'.")?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after import.")?;
+        Ok(Stmt::Import {
+            keyword,
+            path,
+            name,
+        })
+    }
+
+    // classDecl -> "class" IDENTIFIER ( "<" IDENTIFIER )? ( "with" IDENTIFIER ( "," IDENTIFIER )* )? "{" member* "}" ;
+    // member -> function | IDENTIFIER block | "static" function | "static" IDENTIFIER "=" expression ";" ;
+    // `static` is not a keyword, a method can still be called `static`.
+    fn class_decl(&mut self) -> Result<Stmt, Error> {
+        let doc = self.doc();
+        self.consume(TokenType::CLASS, "Expect keyword 'class'.")?;
+        let name = self.consume(TokenType::IDENTIFIER, "Expect class name.")?;
+
+        let super_class = match self.r#match(&[TokenType::LESS]) {
+            true => Some(Expr::Variable {
+                name: self.consume(TokenType::IDENTIFIER, "Expect superclass name.")?,
+            }),
+            false => None,
+        };
+
+        // `with` is not a keyword either, it only means something before the class body.
+        let mut traits = Vec::new();
+        if self.check(&TokenType::IDENTIFIER) && self.peek().lexeme == "with" {
+            self.advance();
+            loop {
+                traits.push(Expr::Variable {
+                    name: self.consume(TokenType::IDENTIFIER, "Expect trait name.")?,
+                });
+                if !self.r#match(&[TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+        let mut methods = Vec::new();
+        let mut getters = Vec::new();
+        let mut static_methods = Vec::new();
+        let mut static_fields = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.end() {
+            let doc = self.doc();
+            if self.check(&TokenType::IDENTIFIER)
+                && self.peek().lexeme == "static"
+                && self.check_at(1, &TokenType::IDENTIFIER)
+            {
+                self.advance();
+                if self.check_at(1, &TokenType::EQUAL) {
+                    let name = self.advance();
+                    self.advance();
+                    let value = self.expression()?;
+                    self.consume(TokenType::SEMICOLON, "Expect ';' after static field.")?;
+                    static_fields.push((name, value));
+                } else {
+                    static_methods.push(self.function("static method", doc)?);
+                }
+            } else if self.check_at(1, &TokenType::LeftBrace) {
+                let name = self.consume(TokenType::IDENTIFIER, "Expect getter name.")?;
+                let body = match self.block()? {
+                    Stmt::Block { stmts } => stmts,
+                    _ => Vec::new(),
+                };
+                getters.push(Stmt::Function {
+                    name,
+                    params: Vec::new(),
+                    return_type: None,
+                    body,
+                    doc,
+                });
+            } else {
+                methods.push(self.function("method", doc)?);
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Stmt::Class {
+            name,
+            super_class,
+            traits,
+            methods,
+            getters,
+            static_methods,
+            static_fields,
+            doc,
+        })
+    }
+
+    // traitDecl -> "trait" IDENTIFIER "{" function* "}" ;
+    fn trait_decl(&mut self) -> Result<Stmt, Error> {
+        let doc = self.doc();
+        self.consume(TokenType::TRAIT, "Expect keyword 'trait'.")?;
+        let name = self.consume(TokenType::IDENTIFIER, "Expect trait name.")?;
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before trait body.")?;
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.end() {
+            let doc = self.doc();
+            methods.push(self.function("method", doc)?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after trait body.")?;
+
+        Ok(Stmt::Trait { name, methods, doc })
     }
 
     // funDecl -> "fun" function ;
-    // function -> IDENTIFIER "(" parameters? ")" block ;
-    // parameters -> IDENTIFIER ( "," IDENTIFIER )* ;
-    // Lox 是动态类型语言，函数形参没有声明类型, 也没有声明返回类型.
-    fn func_decl(&mut self, kind: &str) -> Result<Stmt, Error> {
+    fn func_decl(&mut self) -> Result<Stmt, Error> {
+        let doc = self.doc();
         self.consume(TokenType::FUN, "Expect keyword 'fun'")?;
+        self.function("function", doc)
+    }
+
+    // function -> IDENTIFIER "(" parameters? ")" ( ":" type )? block ;
+    // parameters -> parameter ( "," parameter )* ;
+    // Lox 是动态类型语言，函数形参没有声明类型, 也没有声明返回类型.
+    fn function(&mut self, kind: &str, doc: Option<String>) -> Result<Stmt, Error> {
         let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name.", kind))?;
 
         self.consume(
             TokenType::LeftParen,
             &format!("Expect '(' after {} name.", kind),
         )?;
-        let mut params = Vec::new();
+        let mut params: Vec<Param> = Vec::new();
         if self.peek().t != TokenType::RightParen {
-            params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?);
-
-            while self.r#match(&[TokenType::COMMA]) {
+            loop {
                 if params.len() >= 255 {
                     return Err(Error::ParseError(
                         self.peek().clone(),
                         "Can't have more than 255 parameters.".to_string(),
                     ));
                 }
-                params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?);
+                if params.last().is_some_and(|p| p.rest) {
+                    return Err(Error::ParseError(
+                        self.peek().clone(),
+                        "Rest parameter must be the last parameter.".to_string(),
+                    ));
+                }
+                let param = self.parameter()?;
+                // the arguments fill the parameters in order, so the optional ones come last.
+                if param.default.is_none()
+                    && !param.rest
+                    && params.last().is_some_and(|p| p.default.is_some())
+                {
+                    return Err(Error::ParseError(
+                        param.name,
+                        "Parameter without a default value can't follow one with a default."
+                            .to_string(),
+                    ));
+                }
+                params.push(param);
+                if !self.r#match(&[TokenType::COMMA]) {
+                    break;
+                }
             }
         }
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        let return_type = self.annotation()?;
 
         let body = match self.block()? {
             Stmt::Block { stmts } => stmts,
             _ => Vec::new(),
         };
 
-        Ok(Stmt::Function { name, params, body })
+        Ok(Stmt::Function {
+            name,
+            params,
+            return_type,
+            body,
+            doc,
+        })
+    }
+
+    // parameter -> IDENTIFIER ( ":" type )? ( "=" expression )? | "..." IDENTIFIER ( ":" type )? ;
+    fn parameter(&mut self) -> Result<Param, Error> {
+        let rest = self.r#match(&[TokenType::DotDotDot]);
+        let name = self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?;
+        let annotation = self.annotation()?;
+        let default = if !rest && self.r#match(&[TokenType::EQUAL]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        Ok(Param {
+            name,
+            annotation,
+            default,
+            rest,
+        })
     }
 
-    // statement -> exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt | block ;
+    // statement -> exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt
+    //            | throwStmt | tryStmt | matchStmt | yieldStmt | block ;
     fn statement(&mut self) -> Result<Stmt, Error> {
+        self.nested(Parser::statement_kind)
+    }
+
+    fn statement_kind(&mut self) -> Result<Stmt, Error> {
         match self.peek().t {
+            TokenType::THROW => self.throw_stmt(),
+            TokenType::TRY => self.try_stmt(),
+            TokenType::MATCH => self.match_stmt(),
             TokenType::PRINT => self.print_stmt(),
             TokenType::LeftBrace => self.block(),
             TokenType::IF => self.if_stmt(),
             TokenType::WHILE => self.while_stmt(),
             TokenType::FOR => self.for_stmt(),
             TokenType::RETURN => self.return_stmt(),
+            TokenType::YIELD => self.yield_stmt(),
             _ => self.expr_stmt(),
         }
     }
 
     // printStmt -> "print" expression ";" ;
     fn print_stmt(&mut self) -> Result<Stmt, Error> {
-        self.consume(TokenType::PRINT, "Expect keyword 'PRINT'.")?;
+        let keyword = self.consume(TokenType::PRINT, "Expect keyword 'PRINT'.")?;
         let value = self.expression()?;
         self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
-        Ok(Stmt::Print { expr: value })
+        Ok(Stmt::Print {
+            keyword,
+            expr: value,
+        })
     }
 
     // block -> "{" declaration* "}" ;
@@ -157,6 +483,162 @@ impl Parser {
         })
     }
 
+    // matchStmt -> "match" "(" expression ")" "{" matchCase* "}" ;
+    // matchCase -> "case" pattern ( "," pattern )* ( "if" expression )? "=>" statement ;
+    fn match_stmt(&mut self) -> Result<Stmt, Error> {
+        let keyword = self.consume(TokenType::MATCH, "Expect keyword 'match'.")?;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        let value = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after match value.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before match cases.")?;
+
+        let mut cases = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.end() {
+            self.consume(TokenType::CASE, "Expect 'case'.")?;
+            let mut patterns = vec![self.pattern()?];
+            while self.r#match(&[TokenType::COMMA]) {
+                patterns.push(self.pattern()?);
+            }
+            let guard = match self.r#match(&[TokenType::IF]) {
+                true => Some(self.expression()?),
+                false => None,
+            };
+            self.consume(TokenType::FatArrow, "Expect '=>' after case pattern.")?;
+            let body = self.statement()?;
+            cases.push(MatchCase {
+                patterns,
+                guard,
+                body: Box::new(body),
+            });
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after match cases.")?;
+
+        Ok(Stmt::Match {
+            keyword,
+            value,
+            cases,
+        })
+    }
+
+    // pattern -> "_" | literal | IDENTIFIER | IDENTIFIER "{" ( field ( "," field )* )? "}"
+    //            | "[" ( element ( "," element )* )? "]" ;
+    // element -> pattern | "..." IDENTIFIER ;
+    // field -> IDENTIFIER ( ":" pattern )? ;
+    fn pattern(&mut self) -> Result<Pattern, Error> {
+        if self.r#match(&[TokenType::IDENTIFIER]) {
+            let name = self.previous();
+            if name.lexeme == "_" {
+                return Ok(Pattern::Wildcard);
+            }
+            if !self.r#match(&[TokenType::LeftBrace]) {
+                return Ok(Pattern::Binding { name });
+            }
+
+            let mut fields = Vec::new();
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    let field = self.consume(TokenType::IDENTIFIER, "Expect field name.")?;
+                    let pattern = match self.r#match(&[TokenType::COLON]) {
+                        true => self.pattern()?,
+                        false => Pattern::Binding {
+                            name: field.clone(),
+                        },
+                    };
+                    fields.push((field, pattern));
+                    if !self.r#match(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after field patterns.")?;
+            return Ok(Pattern::Instance {
+                class: Expr::Variable { name },
+                fields,
+            });
+        }
+
+        if self.r#match(&[TokenType::LeftBracket]) {
+            let bracket = self.previous();
+            let (mut elements, mut rest) = (Vec::new(), None);
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    if rest.is_some() {
+                        return Err(Error::ParseError(
+                            self.peek().clone(),
+                            "Rest element must be the last element.".to_string(),
+                        ));
+                    }
+                    match self.r#match(&[TokenType::DotDotDot]) {
+                        true => {
+                            rest = Some(self.consume(TokenType::IDENTIFIER, "Expect rest name.")?)
+                        }
+                        false => elements.push(self.pattern()?),
+                    }
+                    if !self.r#match(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list patterns.")?;
+            return Ok(Pattern::List {
+                bracket,
+                elements,
+                rest,
+            });
+        }
+
+        // a negative number is a negation in expressions, a literal in patterns.
+        if self.r#match(&[TokenType::MINUS]) {
+            let op = self.previous();
+            if is_min_integer(&self.peek().t) {
+                return Ok(Pattern::Literal {
+                    value: Expr::Literal {
+                        value: self.min_integer(op),
+                    },
+                });
+            }
+            return match self.peek().t {
+                TokenType::INTEGER { .. }
+                | TokenType::BIGINT { .. }
+                | TokenType::DECIMAL { .. }
+                | TokenType::NUMBER { .. } => Ok(Pattern::Literal {
+                    value: Expr::Unary {
+                        op,
+                        right: Box::new(Expr::Literal {
+                            value: self.advance(),
+                        }),
+                    },
+                }),
+                _ => Err(Error::ParseError(
+                    self.peek().clone(),
+                    "Expect number after '-' in pattern.".to_string(),
+                )),
+            };
+        }
+
+        if is_min_integer(&self.peek().t) {
+            return Err(too_large(self.peek().clone()));
+        }
+        match self.peek().t {
+            TokenType::TRUE
+            | TokenType::FALSE
+            | TokenType::NIL
+            | TokenType::INTEGER { .. }
+            | TokenType::BIGINT { .. }
+            | TokenType::DECIMAL { .. }
+            | TokenType::NUMBER { .. }
+            | TokenType::STRING { .. } => Ok(Pattern::Literal {
+                value: Expr::Literal {
+                    value: self.advance(),
+                },
+            }),
+            _ => Err(Error::ParseError(
+                self.peek().clone(),
+                "Expect pattern.".to_string(),
+            )),
+        }
+    }
+
     // whileStmt -> "while" "(" expression ")" statement ;
     fn while_stmt(&mut self) -> Result<Stmt, Error> {
         self.consume(TokenType::WHILE, "Expect keyword 'while'.")?;
@@ -170,11 +652,29 @@ impl Parser {
         })
     }
 
-    // forStmt -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+    // forStmt -> "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement
+    //          | "for" "(" "var"? IDENTIFIER "in" expression ")" statement ;
     fn for_stmt(&mut self) -> Result<Stmt, Error> {
         self.consume(TokenType::FOR, "Expect keyword 'for'.")?;
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
 
+        // `for (x in iterable)` or `for (var x in iterable)`, both declare `x` for the body.
+        let skip = usize::from(self.check(&TokenType::VAR));
+        if self.check_at(skip, &TokenType::IDENTIFIER) && self.check_at(skip + 1, &TokenType::IN) {
+            self.cur += skip;
+            let name = self.advance();
+            let keyword = self.advance();
+            let iterable = self.expression()?;
+            self.consume(TokenType::RightParen, "Expect ')' after for-in clause.")?;
+            let body = self.statement()?;
+            return Ok(Stmt::ForIn {
+                name,
+                keyword,
+                iterable,
+                body: Box::new(body),
+            });
+        }
+
         let init = match self.peek().t {
             TokenType::SEMICOLON => {
                 self.advance();
@@ -202,31 +702,22 @@ impl Parser {
         // for (init; condition; increment) body 等价于
         // init;
         // while (condition) {body; increment;}
-        if increment.is_some() {
+        if let Some(increment) = increment {
             body = Stmt::Block {
-                stmts: vec![
-                    body,
-                    Stmt::Expression {
-                        expr: increment.unwrap(),
-                    },
-                ],
+                stmts: vec![body, Stmt::Expression { expr: increment }],
             };
         }
 
         body = Stmt::While {
             condition: condition.unwrap_or(Expr::Literal {
-                value: Token {
-                    t: TokenType::TRUE,
-                    lexeme: String::new(),
-                    line: 0,
-                },
+                value: Token::new(TokenType::TRUE, "", 0, 0),
             }),
             body: Box::new(body),
         };
 
-        if init.is_some() {
+        if let Some(init) = init {
             body = Stmt::Block {
-                stmts: vec![init.unwrap(), body],
+                stmts: vec![init, body],
             };
         }
 
@@ -244,6 +735,57 @@ impl Parser {
         Ok(Stmt::Return { keyword, value })
     }
 
+    // yieldStmt -> "yield" expression? ";" ;
+    fn yield_stmt(&mut self) -> Result<Stmt, Error> {
+        let keyword = self.consume(TokenType::YIELD, "Expect keyword 'yield'.")?;
+        let value = match self.peek().t {
+            TokenType::SEMICOLON => None,
+            _ => Some(self.expression()?),
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after yield value.")?;
+        Ok(Stmt::Yield { keyword, value })
+    }
+
+    // throwStmt -> "throw" expression ";" ;
+    fn throw_stmt(&mut self) -> Result<Stmt, Error> {
+        let keyword = self.consume(TokenType::THROW, "Expect keyword 'throw'.")?;
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw { keyword, value })
+    }
+
+    // tryStmt -> "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+    fn try_stmt(&mut self) -> Result<Stmt, Error> {
+        let keyword = self.consume(TokenType::TRY, "Expect keyword 'try'.")?;
+        let body = self.block()?;
+
+        let catch_clause = match self.r#match(&[TokenType::CATCH]) {
+            true => {
+                self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+                let name = self.consume(TokenType::IDENTIFIER, "Expect error variable name.")?;
+                self.consume(TokenType::RightParen, "Expect ')' after error variable.")?;
+                Some((name, Box::new(self.block()?)))
+            }
+            false => None,
+        };
+        let finally_body = match self.r#match(&[TokenType::FINALLY]) {
+            true => Some(Box::new(self.block()?)),
+            false => None,
+        };
+
+        if catch_clause.is_none() && finally_body.is_none() {
+            return Err(Error::ParseError(
+                keyword,
+                "Expect 'catch' or 'finally' after try block.".to_string(),
+            ));
+        }
+        Ok(Stmt::Try {
+            body: Box::new(body),
+            catch_clause,
+            finally_body,
+        })
+    }
+
     // exprStmt -> expression ";" ;
     fn expr_stmt(&mut self) -> Result<Stmt, Error> {
         let expr = self.expression()?;
@@ -253,18 +795,21 @@ impl Parser {
 
     // expression -> assignment ;
     fn expression(&mut self) -> Result<Expr, Error> {
-        self.assignment()
+        self.nested(Parser::assignment)
     }
 
-    // assignment -> ( call "." )? IDENTIFIER "=" assignment | logic_or ;
+    // assignment -> ( call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER )
+    //               ( "=" | "+=" | "-=" | "*=" | "/=" | "%=" ) assignment
+    //             | "[" ( target ( "," target )* )? "]" "=" assignment | conditional ;
+    // target -> call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER ;
     // logic_or -> logic_and ( "or" logic_and )* ;
     // logic_and -> equality ( "and" equality )* ;
     fn assignment(&mut self) -> Result<Expr, Error> {
-        let expr = self.logical_or()?;
+        let expr = self.conditional()?;
 
         if self.r#match(&[TokenType::EQUAL]) {
             let equal = self.previous();
-            let value = self.assignment()?;
+            let value = self.nested(Parser::assignment)?;
             match expr {
                 Expr::Variable { name } => {
                     return Ok(Expr::Assign {
@@ -272,6 +817,40 @@ impl Parser {
                         value: Box::new(value),
                     })
                 }
+                Expr::Get { object, name } => {
+                    return Ok(Expr::Set {
+                        object,
+                        name,
+                        value: Box::new(value),
+                    })
+                }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    return Ok(Expr::SetIndex {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    })
+                }
+                // `[a, b] = [b, a]`
+                Expr::List { bracket, elements }
+                    if elements.iter().all(|element| {
+                        matches!(
+                            element,
+                            Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. }
+                        )
+                    }) =>
+                {
+                    return Ok(Expr::AssignList {
+                        bracket,
+                        targets: elements,
+                        value: Box::new(value),
+                    })
+                }
                 _ => {
                     return Err(Error::ParseError(
                         equal,
@@ -281,6 +860,65 @@ impl Parser {
             }
         }
 
+        if self.r#match(&[
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+            TokenType::PercentEqual,
+        ]) {
+            let op = self.previous();
+            let value = self.nested(Parser::assignment)?;
+            return match expr {
+                Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. } => {
+                    Ok(Expr::CompoundAssign {
+                        target: Box::new(expr),
+                        op,
+                        value: Box::new(value),
+                    })
+                }
+                _ => Err(Error::ParseError(
+                    op,
+                    "Invalid assignment target.".to_string(),
+                )),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // conditional -> coalesce ( "?" expression ":" conditional )? ;
+    fn conditional(&mut self) -> Result<Expr, Error> {
+        let condition = self.coalesce()?;
+        if self.r#match(&[TokenType::QUESTION]) {
+            let then_expr = self.expression()?;
+            self.consume(
+                TokenType::COLON,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_expr = self.nested(Parser::conditional)?;
+            return Ok(Expr::Conditional {
+                condition: Box::new(condition),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            });
+        }
+        Ok(condition)
+    }
+
+    // coalesce -> logic_or ( "??" logic_or )* ;
+    // unlike `or`, `a ?? b` only falls back to `b` when `a` is nil, not when it's false.
+    fn coalesce(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.logical_or()?;
+        while self.r#match(&[TokenType::QuestionQuestion]) {
+            let op = self.previous();
+            let right = self.logical_or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
         Ok(expr)
     }
 
@@ -328,9 +966,9 @@ impl Parser {
         Ok(expr)
     }
 
-    // comparison -> term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+    // comparison -> bit_or ( ( ">" | ">=" | "<" | "<=" ) bit_or )* ;
     fn comparison(&mut self) -> Result<Expr, Error> {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
 
         while self.r#match(&[
             TokenType::GREATER,
@@ -338,6 +976,72 @@ impl Parser {
             TokenType::LESS,
             TokenType::LessEqual,
         ]) {
+            let op = self.previous();
+            let right = self.bit_or()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    // the bitwise operators bind tighter than comparison (like Python, unlike C),
+    // so `a & mask == 0` means `(a & mask) == 0`.
+    // bit_or -> bit_xor ( "|" bit_xor )* ;
+    fn bit_or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.bit_xor()?;
+
+        while self.r#match(&[TokenType::PIPE]) {
+            let op = self.previous();
+            let right = self.bit_xor()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    // bit_xor -> bit_and ( "^" bit_and )* ;
+    fn bit_xor(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.bit_and()?;
+
+        while self.r#match(&[TokenType::CARET]) {
+            let op = self.previous();
+            let right = self.bit_and()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    // bit_and -> shift ( "&" shift )* ;
+    fn bit_and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.shift()?;
+
+        while self.r#match(&[TokenType::AMPERSAND]) {
+            let op = self.previous();
+            let right = self.shift()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+        Ok(expr)
+    }
+
+    // shift -> term ( ( "<<" | ">>" ) term )* ;
+    fn shift(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.term()?;
+
+        while self.r#match(&[TokenType::LessLess, TokenType::GreaterGreater]) {
             let op = self.previous();
             let right = self.term()?;
             expr = Expr::Binary {
@@ -365,11 +1069,16 @@ impl Parser {
         Ok(expr)
     }
 
-    // factor -> unary ( ( "/" | "*" ) unary )* ;
+    // factor -> unary ( ( "/" | "*" | "%" | "~/" ) unary )* ;
     fn factor(&mut self) -> Result<Expr, Error> {
         let mut expr = self.unary()?;
 
-        while self.r#match(&[TokenType::SLASH, TokenType::STAR]) {
+        while self.r#match(&[
+            TokenType::SLASH,
+            TokenType::STAR,
+            TokenType::PERCENT,
+            TokenType::TildeSlash,
+        ]) {
             let op = self.previous();
             let right = self.unary()?;
             expr = Expr::Binary {
@@ -381,25 +1090,123 @@ impl Parser {
         Ok(expr)
     }
 
-    // unary -> ( "!" | "-" ) unary | call ;
+    // unary -> ( "!" | "-" | "~" ) unary | ( "++" | "--" ) unary | postfix ;
+    // the operand of a prefix `++` or `--` is a variable, property or index. `--` before anything
+    // else reads as two negations, so `--(3)` is 3 as in upstream Lox; there's no unary `+` for
+    // `++` to fall back on, so `++(3)` is an "Invalid increment target." error like `(3)++`.
     fn unary(&mut self) -> Result<Expr, Error> {
-        if self.r#match(&[TokenType::BANG, TokenType::MINUS]) {
+        if self.r#match(&[TokenType::BANG, TokenType::MINUS, TokenType::TILDE]) {
             let op = self.previous();
-            let right = self.unary()?;
+            if op.t == TokenType::MINUS && is_min_integer(&self.peek().t) {
+                return Ok(Expr::Literal {
+                    value: self.min_integer(op),
+                });
+            }
+            // negating the integer 0 gives 0, but a `-0` literal is the float negative zero it
+            // always was in Lox.
+            if op.t == TokenType::MINUS && self.peek().t == (TokenType::INTEGER { literal: 0 }) {
+                self.advance();
+                let zero = TokenType::NUMBER { literal: F64(-0.0) };
+                return Ok(Expr::Literal {
+                    value: Token::new(zero, "-0", op.line, op.offset),
+                });
+            }
+            let right = self.nested(Parser::unary)?;
             return Ok(Expr::Unary {
                 op,
                 right: Box::new(right),
             });
         }
-        self.call()
+        if self.r#match(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let op = self.previous();
+            let right = self.nested(Parser::unary)?;
+            return match right {
+                Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. } => {
+                    Ok(Expr::Increment {
+                        target: Box::new(right),
+                        op,
+                        prefix: true,
+                    })
+                }
+                _ if op.t == TokenType::MinusMinus => {
+                    let minus = Token::new(TokenType::MINUS, "-", op.line, op.offset);
+                    Ok(Expr::Unary {
+                        op: minus.clone(),
+                        right: Box::new(Expr::Unary {
+                            op: minus,
+                            right: Box::new(right),
+                        }),
+                    })
+                }
+                _ => Err(Error::ParseError(
+                    op,
+                    "Invalid increment target.".to_string(),
+                )),
+            };
+        }
+        self.postfix()
     }
 
-    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
-    // TODO: "." IDENTIFIER
+    // postfix -> call ( "++" | "--" )? ;
+    fn postfix(&mut self) -> Result<Expr, Error> {
+        let expr = self.call()?;
+        if self.r#match(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let op = self.previous();
+            return match expr {
+                Expr::Variable { .. } | Expr::Get { .. } | Expr::Index { .. } => {
+                    Ok(Expr::Increment {
+                        target: Box::new(expr),
+                        op,
+                        prefix: false,
+                    })
+                }
+                _ => Err(Error::ParseError(
+                    op,
+                    "Invalid increment target.".to_string(),
+                )),
+            };
+        }
+        Ok(expr)
+    }
+
+    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER | "?." IDENTIFIER | "[" expression "]" )* ;
     fn call(&mut self) -> Result<Expr, Error> {
         let mut expr = self.primary()?;
-        while self.r#match(&[TokenType::LeftParen]) {
-            expr = self.finish_call(expr)?;
+        let mut optional = false;
+        loop {
+            if self.r#match(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.r#match(&[TokenType::DOT]) {
+                let name =
+                    self.consume(TokenType::IDENTIFIER, "Expect property name after '.'.")?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.r#match(&[TokenType::QuestionDot]) {
+                optional = true;
+                let name =
+                    self.consume(TokenType::IDENTIFIER, "Expect property name after '?.'.")?;
+                expr = Expr::OptionalGet {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.r#match(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
+            } else {
+                break;
+            }
+        }
+        if optional {
+            expr = Expr::OptionalChain {
+                chain: Box::new(expr),
+            };
         }
         Ok(expr)
     }
@@ -411,7 +1218,7 @@ impl Parser {
         // 3. arg1, arg2, arg3...)
         let mut args = Vec::new();
         if self.peek().t != TokenType::RightParen {
-            args.push(self.expression()?);
+            args.push(self.argument()?);
         }
         while self.r#match(&[TokenType::COMMA]) {
             if args.len() >= 255 {
@@ -420,7 +1227,7 @@ impl Parser {
                     "Can't have more than 255 arguments.".to_string(),
                 ));
             }
-            args.push(self.expression()?);
+            args.push(self.argument()?);
         }
         let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
         Ok(Expr::Call {
@@ -430,25 +1237,64 @@ impl Parser {
         })
     }
 
-    // primary -> "true" | "false" | "nil" | "this" | NUMBER | STRING | IDENTIFIER | "(" expression ")"
-    //            | "super" "." IDENTIFIER ;
+    // argument -> "..."? expression ;
+    fn argument(&mut self) -> Result<Expr, Error> {
+        if self.r#match(&[TokenType::DotDotDot]) {
+            let dots = self.previous();
+            let value = self.expression()?;
+            return Ok(Expr::Spread {
+                dots,
+                value: Box::new(value),
+            });
+        }
+        self.expression()
+    }
+
+    // primary -> "true" | "false" | "nil" | "this" | INTEGER | BIGINT | DECIMAL | NUMBER | STRING | IDENTIFIER | "(" expression ")"
+    //            | "super" "." IDENTIFIER | "[" arguments? "]" | "{" ( entry ( "," entry )* )? "}" ;
     fn primary(&mut self) -> Result<Expr, Error> {
         for t in [
             TokenType::TRUE,
             TokenType::FALSE,
             TokenType::NIL,
+            TokenType::INTEGER { literal: 0 },
+            TokenType::BIGINT {
+                literal: BigInt::zero(),
+            },
+            TokenType::DECIMAL {
+                literal: Decimal::from_bigint(BigInt::zero()),
+            },
             TokenType::NUMBER { literal: F64(0.0) },
             TokenType::STRING {
                 literal: "".to_string(),
             },
         ] {
             if self.r#match(&[t]) {
+                if is_min_integer(&self.previous().t) {
+                    return Err(too_large(self.previous()));
+                }
                 return Ok(Expr::Literal {
                     value: self.previous(),
                 });
             }
         }
 
+        // `Fiber` isn't a keyword, `Fiber.yield(value)` is the only way to yield from a fiber.
+        if self.peek().lexeme == "Fiber"
+            && self.check_at(1, &TokenType::DOT)
+            && self.check_at(2, &TokenType::YIELD)
+        {
+            self.cur += 2;
+            let keyword = self.advance();
+            self.consume(TokenType::LeftParen, "Expect '(' after 'Fiber.yield'.")?;
+            let value = match self.check(&TokenType::RightParen) {
+                true => None,
+                false => Some(Box::new(self.expression()?)),
+            };
+            self.consume(TokenType::RightParen, "Expect ')' after yield value.")?;
+            return Ok(Expr::FiberYield { keyword, value });
+        }
+
         if self.r#match(&[TokenType::IDENTIFIER]) {
             return Ok(Expr::Variable {
                 name: self.previous(),
@@ -463,7 +1309,68 @@ impl Parser {
             });
         }
 
-        panic!("this | super | id is unimplemented.");
+        if self.r#match(&[TokenType::LeftBracket]) {
+            let bracket = self.previous();
+            let mut elements = Vec::new();
+            if !self.check(&TokenType::RightBracket) {
+                elements.push(self.argument()?);
+                while self.r#match(&[TokenType::COMMA]) {
+                    elements.push(self.argument()?);
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            return Ok(Expr::List { bracket, elements });
+        }
+
+        // in an expression, `{` starts a map, a block can't appear there.
+        if self.r#match(&[TokenType::LeftBrace]) {
+            let brace = self.previous();
+            let mut entries = Vec::new();
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::COLON, "Expect ':' after map key.")?;
+                    entries.push((key, self.expression()?));
+                    if !self.r#match(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map { brace, entries });
+        }
+
+        if self.r#match(&[TokenType::THIS]) {
+            return Ok(Expr::This {
+                keyword: self.previous(),
+            });
+        }
+
+        if self.r#match(&[TokenType::SUPER]) {
+            let keyword = self.previous();
+            self.consume(TokenType::DOT, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::IDENTIFIER, "Expect superclass method name.")?;
+            return Ok(Expr::Super { keyword, method });
+        }
+
+        Err(Error::ParseError(
+            self.peek().clone(),
+            "Expect expression.".to_string(),
+        ))
+    }
+
+    // parse one level deeper, past `MAX_NESTING` it's an error instead of a stack overflow.
+    fn nested<T>(&mut self, parse: fn(&mut Parser) -> Result<T, Error>) -> Result<T, Error> {
+        if self.nesting >= MAX_NESTING {
+            return Err(Error::ParseError(
+                self.peek().clone(),
+                "Too much nesting.".to_string(),
+            ));
+        }
+        self.nesting += 1;
+        let res = parse(self);
+        self.nesting -= 1;
+        res
     }
 
     fn consume(&mut self, t: TokenType, msg: &str) -> Result<Token, Error> {
@@ -482,13 +1389,20 @@ impl Parser {
             }
             match &self.peek().t {
                 TokenType::CLASS
+                | TokenType::TRAIT
                 | TokenType::FUN
                 | TokenType::VAR
+                | TokenType::CONST
                 | TokenType::FOR
                 | TokenType::IF
                 | TokenType::WHILE
                 | TokenType::PRINT
-                | TokenType::RETURN => return,
+                | TokenType::RETURN
+                | TokenType::YIELD
+                | TokenType::IMPORT
+                | TokenType::MATCH
+                | TokenType::THROW
+                | TokenType::TRY => return,
                 _ => (),
             }
             self.advance();
@@ -512,6 +1426,18 @@ impl Parser {
         let peek = self.peek().clone().t;
 
         match peek {
+            TokenType::INTEGER { .. } => match t {
+                TokenType::INTEGER { .. } => return true,
+                _ => return false,
+            },
+            TokenType::BIGINT { .. } => match t {
+                TokenType::BIGINT { .. } => return true,
+                _ => return false,
+            },
+            TokenType::DECIMAL { .. } => match t {
+                TokenType::DECIMAL { .. } => return true,
+                _ => return false,
+            },
             TokenType::NUMBER { .. } => match t {
                 TokenType::NUMBER { .. } => return true,
                 _ => return false,
@@ -532,6 +1458,14 @@ impl Parser {
         self.previous()
     }
 
+    // whether the token `distance` tokens after the current one is of the type.
+    fn check_at(&self, distance: usize, t: &TokenType) -> bool {
+        match self.tokens.get(self.cur + distance) {
+            Some(token) => token.t == *t,
+            None => false,
+        }
+    }
+
     fn peek(&self) -> &Token {
         &self.tokens[self.cur]
     }
@@ -543,4 +1477,24 @@ impl Parser {
     fn previous(&self) -> Token {
         self.tokens[self.cur - 1].clone()
     }
+
+    // `-9223372036854775808`: the minus and 2^63, which the scanner reads as `i64::MIN`, make one literal.
+    fn min_integer(&mut self, minus: Token) -> Token {
+        let literal = self.advance();
+        Token::new(
+            literal.t,
+            &format!("-{}", literal.lexeme),
+            minus.line,
+            minus.offset,
+        )
+    }
+}
+
+fn is_min_integer(t: &TokenType) -> bool {
+    *t == TokenType::INTEGER { literal: i64::MIN }
+}
+
+// 2^63 without a `-` before it.
+fn too_large(token: Token) -> Error {
+    Error::ParseError(token, "Integer literal too large.".to_string())
 }
@@ -1,24 +1,44 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
-    ast::{Expr, Stmt},
+    ast::{Expr, Param, Pattern, Stmt},
     error::Error,
     token::Token,
 };
 
-pub fn resolve_variable(stmts: &Vec<Stmt>) -> Option<HashMap<Expr, usize>> {
+// what the interpreter needs to know about a file before running it.
+pub struct Resolution {
+    pub depths: HashMap<Expr, usize>,
+    pub tail_calls: HashSet<Expr>, // the calls of `return f(x);`, they run in the caller's frame.
+}
+
+pub fn resolve_variable(stmts: &Vec<Stmt>) -> Option<Resolution> {
     let mut resolver = Resolver::new();
     resolver.resolve_stmts(stmts);
     if resolver.has_err {
         return None;
     }
-    Some(resolver.depths)
+    Some(Resolution {
+        depths: resolver.depths,
+        tail_calls: resolver.tail_calls,
+    })
 }
 
 #[derive(Clone, Copy, PartialEq)]
 enum FuncType {
-    NONE,
-    FUNCTION,
+    None,
+    Function,
+    Method,
+    Initializer, // the `init` method of a class.
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass, // `this` and `super` are both usable.
+    Static,   // a static method or field initializer, there's no instance.
+    Trait,    // a trait method, `this` is the instance of a class using the trait.
 }
 
 // 执行 ast 时，对函数闭包的捕获有一个 bug: 捕获的闭包后续的修改可能会导致函数读取到错误的变量/函数.
@@ -45,8 +65,14 @@ enum FuncType {
 // 4. 读取变量的表达式会解析变量的值.
 struct Resolver {
     scopes: Vec<HashMap<String, bool>>,
+    constants: Vec<HashSet<String>>, // the `const` names of each scope.
     curr_func: FuncType,
+    curr_class: ClassType,
+    in_generator: bool, // the current function has a `yield` in its body.
+    in_try: bool,       // a generator can't suspend inside a `try` statement of its body.
     depths: HashMap<Expr, usize>,
+    tail_calls: HashSet<Expr>,
+    traits: HashMap<String, Vec<String>>, // the method names of the traits declared so far.
     has_err: bool,
 }
 
@@ -54,8 +80,14 @@ impl Resolver {
     fn new() -> Self {
         Self {
             scopes: Vec::new(),
-            curr_func: FuncType::NONE,
+            constants: Vec::new(),
+            curr_func: FuncType::None,
+            curr_class: ClassType::None,
+            in_generator: false,
+            in_try: false,
             depths: HashMap::new(),
+            tail_calls: HashSet::new(),
+            traits: HashMap::new(),
             has_err: false,
         }
     }
@@ -73,17 +105,48 @@ impl Resolver {
                 self.resolve_stmts(stmts);
                 self.end_scope();
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var {
+                name,
+                initializer,
+                constant,
+                ..
+            } => {
                 self.declare(name);
                 if let Some(init) = initializer {
                     self.resolve_expr(init);
                 }
                 self.define(name);
+                if *constant {
+                    if let Some(constants) = self.constants.last_mut() {
+                        constants.insert(name.lexeme.clone());
+                    }
+                }
+            }
+            Stmt::Destructure {
+                target,
+                initializer,
+                constant,
+            } => {
+                let names = target.names();
+                for name in &names {
+                    self.declare(name);
+                }
+                self.resolve_expr(initializer);
+                for name in names {
+                    self.define(name);
+                    if *constant {
+                        if let Some(constants) = self.constants.last_mut() {
+                            constants.insert(name.lexeme.clone());
+                        }
+                    }
+                }
             }
-            Stmt::Function { name, params, body } => {
+            Stmt::Function {
+                name, params, body, ..
+            } => {
                 self.declare(name);
                 self.define(name);
-                self.resolve_func(params, body, FuncType::FUNCTION);
+                self.resolve_func(params, body, FuncType::Function);
             }
             Stmt::Expression { expr } => {
                 self.resolve_expr(expr);
@@ -99,18 +162,38 @@ impl Resolver {
                     self.resolve_stmt(else_branch);
                 }
             }
-            Stmt::Print { expr } => self.resolve_expr(expr),
+            Stmt::Print { expr, .. } => self.resolve_expr(expr),
             Stmt::Return { keyword, value } => {
                 // return 语句在函数内部才有意义.
-                if self.curr_func == FuncType::NONE {
-                    eprintln!(
-                        "{}",
-                        Error::ParseError(
-                            keyword.clone(),
-                            "Can't return from top-level code.".to_string(),
-                        )
-                    );
-                    self.has_err = true;
+                if self.curr_func == FuncType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    // `init` always returns the instance.
+                    if self.curr_func == FuncType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    // a generator's values are the ones it yields.
+                    if self.in_generator {
+                        self.error(keyword, "Can't return a value from a generator.");
+                    }
+                    // nothing is left to do in the function after the call, except in a `try`
+                    // that may catch its error or run a `finally`.
+                    let tail = matches!(self.curr_func, FuncType::Function | FuncType::Method);
+                    if tail && !self.in_try && matches!(value, Expr::Call { .. }) {
+                        self.tail_calls.insert(value.clone());
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Yield { keyword, value } => {
+                match self.curr_func {
+                    FuncType::None => self.error(keyword, "Can't yield from top-level code."),
+                    FuncType::Initializer => {
+                        self.error(keyword, "Can't yield from an initializer.")
+                    }
+                    _ if self.in_try => self.error(keyword, "Can't yield inside a try statement."),
+                    _ => (),
                 }
                 if let Some(value) = value {
                     self.resolve_expr(value);
@@ -120,7 +203,169 @@ impl Resolver {
                 self.resolve_expr(condition);
                 self.resolve_stmt(body);
             }
-            Stmt::Class { .. } => todo!(),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.resolve_expr(iterable);
+                // the interpreter binds the loop variable in a new env for every iteration.
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.resolve_stmt(body);
+                self.end_scope();
+            }
+            Stmt::Import { name, .. } => {
+                self.declare(name);
+                self.define(name);
+            }
+            Stmt::Match { value, cases, .. } => {
+                self.resolve_expr(value);
+                for case in cases {
+                    // like a block, every case has a scope of its own for its bindings, guard and body.
+                    self.begin_scope();
+                    // a name bound by one alternative would be unbound when another one matches.
+                    if case.patterns.len() > 1 {
+                        if let Some(name) = case.patterns.iter().find_map(Pattern::binding) {
+                            self.error(&name, "Can't bind names in alternative patterns.");
+                        }
+                    }
+                    for pattern in &case.patterns {
+                        self.resolve_pattern(pattern);
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_stmt(&case.body);
+                    self.end_scope();
+                }
+            }
+            Stmt::Throw { keyword: _, value } => self.resolve_expr(value),
+            Stmt::Try {
+                body,
+                catch_clause,
+                finally_body,
+            } => {
+                let enclosing_try = self.in_try;
+                self.in_try = true;
+                self.resolve_stmt(body);
+                if let Some((name, catch_body)) = catch_clause {
+                    // the interpreter binds the error in a scope of its own around the catch block.
+                    self.begin_scope();
+                    self.declare(name);
+                    self.define(name);
+                    self.resolve_stmt(catch_body);
+                    self.end_scope();
+                }
+                if let Some(finally_body) = finally_body {
+                    self.resolve_stmt(finally_body);
+                }
+                self.in_try = enclosing_try;
+            }
+            Stmt::Trait { name, methods, .. } => {
+                self.declare(name);
+                self.define(name);
+                let mut names = Vec::new();
+
+                let enclosing_class = self.curr_class;
+                self.curr_class = ClassType::Trait;
+                // the class using the trait binds `this`, like for its own methods.
+                self.begin_scope();
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert("this".to_string(), true);
+                for method in methods {
+                    if let Stmt::Function {
+                        name, params, body, ..
+                    } = method
+                    {
+                        if name.lexeme == "init" {
+                            self.error(name, "A trait can't have an initializer.");
+                        }
+                        names.push(name.lexeme.clone());
+                        self.resolve_func(params, body, FuncType::Method);
+                    }
+                }
+                self.end_scope();
+                self.curr_class = enclosing_class;
+
+                self.traits.insert(name.lexeme.clone(), names);
+            }
+            Stmt::Class {
+                name,
+                super_class,
+                traits,
+                methods,
+                getters,
+                static_methods,
+                static_fields,
+                ..
+            } => {
+                let enclosing_class = self.curr_class;
+                self.curr_class = ClassType::Class;
+                self.declare(name);
+                self.define(name);
+
+                for t in traits {
+                    self.resolve_expr(t);
+                }
+                self.check_traits(name, traits, methods);
+
+                if let Some(super_class) = super_class {
+                    if let Expr::Variable { name: super_name } = super_class {
+                        if super_name.lexeme == name.lexeme {
+                            self.error(super_name, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.curr_class = ClassType::Subclass;
+                    self.resolve_expr(super_class);
+                    // the interpreter binds `super` in a scope around the methods.
+                    self.begin_scope();
+                    self.scopes
+                        .last_mut()
+                        .unwrap()
+                        .insert("super".to_string(), true);
+                }
+
+                // and `this` in a scope of its own when a method is bound to an instance.
+                self.begin_scope();
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert("this".to_string(), true);
+                for method in methods.iter().chain(getters) {
+                    if let Stmt::Function {
+                        name, params, body, ..
+                    } = method
+                    {
+                        let func_type = match name.lexeme.as_str() {
+                            "init" => FuncType::Initializer,
+                            _ => FuncType::Method,
+                        };
+                        self.resolve_func(params, body, func_type);
+                    }
+                }
+                self.end_scope();
+
+                if super_class.is_some() {
+                    self.end_scope();
+                }
+
+                // the static members live in the scope of the class declaration itself.
+                self.curr_class = ClassType::Static;
+                for method in static_methods {
+                    if let Stmt::Function { params, body, .. } = method {
+                        self.resolve_func(params, body, FuncType::Function);
+                    }
+                }
+                for (_, value) in static_fields {
+                    self.resolve_expr(value);
+                }
+                self.curr_class = enclosing_class;
+            }
         }
     }
 
@@ -135,20 +380,23 @@ impl Resolver {
                         .get(&name.lexeme)
                         .unwrap_or(&true)
                 {
-                    eprintln!(
-                        "{}",
-                        Error::ParseError(
-                            name.clone(),
-                            "Can't read local variable in its own initializer.".to_string(),
-                        )
-                    );
-                    self.has_err = true;
+                    self.error(name, "Can't read local variable in its own initializer.");
                 }
                 self.resolve_local(expr, name);
             }
             Expr::Assign { name, value } => {
                 self.resolve_expr(value);
                 self.resolve_local(expr, name);
+                self.check_assign(name);
+            }
+            Expr::AssignList { targets, value, .. } => {
+                self.resolve_expr(value);
+                for target in targets {
+                    self.resolve_expr(target);
+                    if let Expr::Variable { name } = target {
+                        self.check_assign(name);
+                    }
+                }
             }
             Expr::Binary { left, op: _, right } => {
                 self.resolve_expr(left);
@@ -164,14 +412,145 @@ impl Resolver {
                     self.resolve_expr(arg);
                 }
             }
-            Expr::Grouping { expression: _ } => self.resolve_expr(expr),
+            Expr::Spread { value, .. } => self.resolve_expr(value),
+            Expr::Grouping { expression } => self.resolve_expr(expression),
             Expr::Literal { value: _ } => (),
             Expr::Logical { left, op: _, right } => {
                 self.resolve_expr(left);
                 self.resolve_expr(right);
             }
             Expr::Unary { op: _, right } => self.resolve_expr(right),
-            _ => todo!(),
+            Expr::Get { object, name: _ } => self.resolve_expr(object),
+            Expr::OptionalGet { object, name: _ } => self.resolve_expr(object),
+            Expr::OptionalChain { chain } => self.resolve_expr(chain),
+            Expr::Conditional {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then_expr);
+                self.resolve_expr(else_expr);
+            }
+            Expr::Set {
+                object,
+                name: _,
+                value,
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::CompoundAssign {
+                target,
+                op: _,
+                value,
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(target);
+                if let Expr::Variable { name } = target.as_ref() {
+                    self.check_assign(name);
+                }
+            }
+            Expr::Increment { target, .. } => {
+                self.resolve_expr(target);
+                if let Expr::Variable { name } = target.as_ref() {
+                    self.check_assign(name);
+                }
+            }
+            Expr::This { keyword } => {
+                match self.curr_class {
+                    ClassType::None => {
+                        self.error(keyword, "Can't use 'this' outside of a class.");
+                        return;
+                    }
+                    ClassType::Static => {
+                        self.error(keyword, "Can't use 'this' in a static method.");
+                        return;
+                    }
+                    ClassType::Class | ClassType::Subclass | ClassType::Trait => (),
+                }
+                self.resolve_local(expr, keyword);
+            }
+            Expr::FiberYield { keyword, value } => {
+                // the main script never runs in a fiber, a function may.
+                if self.curr_func == FuncType::None {
+                    self.error(keyword, "Can't yield from top-level code.");
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Super { keyword, method: _ } => {
+                match self.curr_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => {
+                        self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                    }
+                    ClassType::Static => {
+                        self.error(keyword, "Can't use 'super' in a static method.")
+                    }
+                    ClassType::Trait => self.error(keyword, "Can't use 'super' in a trait."),
+                    ClassType::Subclass => (),
+                }
+                self.resolve_local(expr, keyword);
+            }
+        }
+    }
+
+    // report a compile error, resolving goes on to find more of them.
+    fn error(&mut self, token: &Token, msg: &str) {
+        eprintln!("{}", Error::ParseError(token.clone(), msg.to_string()));
+        self.has_err = true;
+    }
+
+    fn resolve_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => (),
+            Pattern::Literal { value } => self.resolve_expr(value),
+            Pattern::Binding { name } => {
+                self.declare(name);
+                self.define(name);
+            }
+            Pattern::Instance { class, fields } => {
+                self.resolve_expr(class);
+                for (_, pattern) in fields {
+                    self.resolve_pattern(pattern);
+                }
+            }
+            Pattern::List { elements, rest, .. } => {
+                for pattern in elements {
+                    self.resolve_pattern(pattern);
+                }
+                if let Some(rest) = rest.as_ref().filter(|rest| rest.lexeme != "_") {
+                    self.declare(rest);
+                    self.define(rest);
+                }
+            }
         }
     }
 
@@ -185,47 +564,98 @@ impl Resolver {
         }
     }
 
-    fn resolve_func(&mut self, params: &Vec<Token>, body: &Vec<Stmt>, func_type: FuncType) {
+    // a method the class doesn't declare itself can come from one trait only,
+    // the class overrides a method two of its traits declare to pick one.
+    // traits the resolver hasn't seen, like an imported one, are checked at runtime.
+    fn check_traits(&mut self, class: &Token, traits: &[Expr], methods: &[Stmt]) {
+        let mut from: HashMap<String, Token> = HashMap::new();
+        let mut conflicts = Vec::new();
+        for t in traits {
+            let Expr::Variable { name: trait_name } = t else {
+                continue;
+            };
+            let Some(names) = self.traits.get(&trait_name.lexeme) else {
+                continue;
+            };
+            for method in names {
+                let overridden = methods.iter().any(|m| match m {
+                    Stmt::Function { name, .. } => &name.lexeme == method,
+                    _ => false,
+                });
+                if overridden {
+                    continue;
+                }
+                if let Some(other) = from.insert(method.clone(), trait_name.clone()) {
+                    conflicts.push(format!(
+                        "Method '{}' is defined by both traits '{}' and '{}'.",
+                        method, other.lexeme, trait_name.lexeme
+                    ));
+                }
+            }
+        }
+        for msg in conflicts {
+            self.error(class, &msg);
+        }
+    }
+
+    // a local constant can't be assigned, globals are checked at runtime.
+    fn check_assign(&mut self, name: &Token) {
+        let scope = (0..self.scopes.len())
+            .rev()
+            .find(|&i| self.scopes[i].contains_key(&name.lexeme));
+        if scope.is_some_and(|i| self.constants[i].contains(&name.lexeme)) {
+            self.error(
+                name,
+                &format!("Can't assign to constant '{}'.", name.lexeme),
+            );
+        }
+    }
+
+    fn resolve_func(&mut self, params: &Vec<Param>, body: &Vec<Stmt>, func_type: FuncType) {
         let enclosing_func = self.curr_func;
+        let (enclosing_generator, enclosing_try) = (self.in_generator, self.in_try);
         self.curr_func = func_type;
+        self.in_generator = body.iter().any(Stmt::contains_yield);
+        self.in_try = false;
         self.begin_scope();
         for param in params {
-            self.declare(param);
-            self.define(param);
+            // a default value sees the parameters before it.
+            if let Some(default) = &param.default {
+                self.resolve_expr(default);
+            }
+            self.declare(&param.name);
+            self.define(&param.name);
         }
         self.resolve_stmts(body);
         self.end_scope();
         self.curr_func = enclosing_func;
+        (self.in_generator, self.in_try) = (enclosing_generator, enclosing_try);
     }
 
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.constants.push(HashSet::new());
     }
 
     fn end_scope(&mut self) {
         self.scopes.pop();
+        self.constants.pop();
     }
 
     fn declare(&mut self, name: &Token) {
         if self.scopes.is_empty() {
             return;
         }
-        let cur = self.scopes.last_mut().unwrap();
-
         // Lox 不允许在 local scope 重复定义变量 (和大部分语言一样)，
         // 但是 Lox 允许在 global scope 重复定义变量，这点设计不太赞同.
-        if cur.contains_key(&name.lexeme) {
-            eprintln!(
-                "{}",
-                Error::ParseError(
-                    name.clone(),
-                    "Already a variable with this name is this scope.".to_string(),
-                )
-            );
-            self.has_err = true;
+        if self.scopes.last().unwrap().contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name is this scope.");
         }
 
-        cur.insert(name.lexeme.clone(), false);
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), false);
     }
 
     fn define(&mut self, name: &Token) {
@@ -1,17 +1,28 @@
+use crate::bignum::{BigInt, Decimal};
 use crate::error;
 use crate::token::{self, Token, TokenType, F64};
 
 // the Lox language defines the lexical grammar:
-//   NUMBER -> DIGIT+ ("." DIGIT+)? ;
+//   BIGINT -> INTEGER "n" ;
+//   DECIMAL -> ( DIGITS | NUMBER ) "d" ;
+//   INTEGER -> DIGITS | "0" ("x" | "X") HEX_DIGITS | "0" ("o" | "O") OCT_DIGITS | "0" ("b" | "B") BIN_DIGITS ;
+//   NUMBER -> DIGITS ( "." DIGITS | ("." DIGITS)? ("e" | "E") ("+" | "-")? DIGITS ) ;
+//   DIGITS -> DIGIT ("_"? DIGIT)* ; (likewise HEX_DIGITS, OCT_DIGITS and BIN_DIGITS)
 //   STRING -> "\"" <any char except "\"">* "\"" ;
 //   IDENTIFIER -> ALPHA (ALPHA | DIGIT)* ;
-//   ALPHA -> "a"..."z" |  "A"..."Z" | "_" ;
+//   ALPHA -> "a"..."z" |  "A"..."Z" | "_" | <any char where char::is_alphabetic()> ;
 //   DIGIT -> "0"..."9" ;
+// comments are skipped, except doc comments which become `DocComment` tokens:
+//   "//" <any char except "\n">*                 line comment
+//   "///" <any char except "\n">*                doc comment (but "////" is a line comment)
+//   "/*" (<any char> | block comment)* "*/"    block comment, may nest
 // Scanner uses the lexical grammar to transform the source code into tokens.
+// `start` and `cur` are byte offsets into `src`, always on a char boundary, so reading the
+// next char is O(1) and the whole scan is linear.
 struct Scanner {
     src: String,
-    start: usize,
-    cur: usize,
+    start: usize, // byte offset of the first char of the current lexeme
+    cur: usize,   // byte offset of the char being considered
     line: u32,
     tokens: Vec<Token>,
     has_err: bool,
@@ -44,50 +55,121 @@ impl Scanner {
             self.scan_next();
         }
         self.tokens
-            .push(Token::new(token::TokenType::EOF, "", self.line));
+            .push(Token::new(token::TokenType::EOF, "", self.line, self.cur));
 
         &self.tokens
     }
 
     fn scan_next(&mut self) {
         let c = self.advance();
-        match c as char {
+        match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::COMMA),
-            '.' => self.add_token(TokenType::DOT),
-            '-' => self.add_token(TokenType::MINUS),
-            '+' => self.add_token(TokenType::PLUS),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.cur += 2;
+                    self.add_token(TokenType::DotDotDot)
+                } else {
+                    self.add_token(TokenType::DOT)
+                }
+            }
+            '-' => {
+                if self.r#match('-') {
+                    self.add_token(TokenType::MinusMinus)
+                } else if self.r#match('=') {
+                    self.add_token(TokenType::MinusEqual)
+                } else {
+                    self.add_token(TokenType::MINUS)
+                }
+            }
+            '+' => {
+                if self.r#match('+') {
+                    self.add_token(TokenType::PlusPlus)
+                } else if self.r#match('=') {
+                    self.add_token(TokenType::PlusEqual)
+                } else {
+                    self.add_token(TokenType::PLUS)
+                }
+            }
             ';' => self.add_token(TokenType::SEMICOLON),
-            '*' => self.add_token(TokenType::STAR),
-            '!' => match self.r#match('=') {
-                true => self.add_token(TokenType::BangEqual),
-                false => self.add_token(TokenType::BANG),
+            '*' => match self.r#match('=') {
+                true => self.add_token(TokenType::StarEqual),
+                false => self.add_token(TokenType::STAR),
             },
-            '=' => match self.r#match('=') {
-                true => self.add_token(TokenType::EqualEqual),
-                false => self.add_token(TokenType::EQUAL),
+            '%' => match self.r#match('=') {
+                true => self.add_token(TokenType::PercentEqual),
+                false => self.add_token(TokenType::PERCENT),
             },
-            '<' => match self.r#match('=') {
-                true => self.add_token(TokenType::LessEqual),
-                false => self.add_token(TokenType::LESS),
+            '&' => self.add_token(TokenType::AMPERSAND),
+            '|' => self.add_token(TokenType::PIPE),
+            '^' => self.add_token(TokenType::CARET),
+            ':' => self.add_token(TokenType::COLON),
+            '?' => {
+                if self.r#match('?') {
+                    self.add_token(TokenType::QuestionQuestion)
+                } else if self.r#match('.') {
+                    self.add_token(TokenType::QuestionDot)
+                } else {
+                    self.add_token(TokenType::QUESTION)
+                }
+            }
+            '~' => match self.r#match('/') {
+                true => self.add_token(TokenType::TildeSlash),
+                false => self.add_token(TokenType::TILDE),
             },
-            '>' => match self.r#match('=') {
-                true => self.add_token(TokenType::GreaterEqual),
-                false => self.add_token(TokenType::GREATER),
+            '!' => match self.r#match('=') {
+                true => self.add_token(TokenType::BangEqual),
+                false => self.add_token(TokenType::BANG),
             },
-            '/' => match self.r#match('/') {
-                true => {
-                    // comments are lexemes, but they aren't meaningful, and the parser doesn't want to deal
-                    // with them, so we don't call add_token()
-                    while !self.end() && self.peek() != '\n' {
-                        self.advance();
+            '=' => {
+                if self.r#match('=') {
+                    self.add_token(TokenType::EqualEqual)
+                } else if self.r#match('>') {
+                    self.add_token(TokenType::FatArrow)
+                } else {
+                    self.add_token(TokenType::EQUAL)
+                }
+            }
+            '<' => {
+                if self.r#match('=') {
+                    self.add_token(TokenType::LessEqual)
+                } else if self.r#match('<') {
+                    self.add_token(TokenType::LessLess)
+                } else {
+                    self.add_token(TokenType::LESS)
+                }
+            }
+            '>' => {
+                if self.r#match('=') {
+                    self.add_token(TokenType::GreaterEqual)
+                } else if self.r#match('>') {
+                    self.add_token(TokenType::GreaterGreater)
+                } else {
+                    self.add_token(TokenType::GREATER)
+                }
+            }
+            '/' => {
+                if self.r#match('/') {
+                    if self.peek() == '/' && self.peek_next() != '/' {
+                        self.doc_comment();
+                    } else {
+                        // comments are lexemes, but they aren't meaningful, and the parser doesn't want to deal
+                        // with them, so we don't call add_token()
+                        self.skip_line();
                     }
+                } else if self.r#match('*') {
+                    self.block_comment();
+                } else if self.r#match('=') {
+                    self.add_token(TokenType::SlashEqual);
+                } else {
+                    self.add_token(TokenType::SLASH);
                 }
-                false => self.add_token(TokenType::SLASH),
-            },
+            }
             ' ' | '\r' | '\t' => (), // ignore whitespace
             '\n' => {
                 self.line += 1;
@@ -114,36 +196,62 @@ impl Scanner {
     }
 
     fn advance(&mut self) -> char {
-        let ch = self.src.bytes().nth(self.cur).unwrap();
-        self.cur += 1;
-        ch as char
+        let ch = self.peek();
+        self.cur += ch.len_utf8();
+        ch
     }
 
     fn peek(&self) -> char {
-        if self.end() {
-            return '\0';
-        }
-        self.src.bytes().nth(self.cur).unwrap() as char
+        self.src[self.cur..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.cur + 1 >= self.src.len() {
-            return '\0';
-        }
-        self.src.bytes().nth(self.cur + 1).unwrap() as char
+        self.src[self.cur..].chars().nth(1).unwrap_or('\0')
     }
 
     fn r#match(&mut self, expected: char) -> bool {
-        if self.end() {
-            return false;
-        }
-        if self.src.bytes().nth(self.cur).unwrap() as char != expected {
+        if self.end() || self.peek() != expected {
             return false;
         }
-        self.cur += 1;
+        self.cur += expected.len_utf8();
         true
     }
 
+    fn skip_line(&mut self) {
+        while !self.end() && self.peek() != '\n' {
+            self.advance();
+        }
+    }
+
+    // `/// text` documents the declaration that follows it, the parser attaches it to the ast.
+    fn doc_comment(&mut self) {
+        self.advance(); // the third '/'.
+        self.skip_line();
+        let text = &self.src[self.start + 3..self.cur];
+        let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+        self.add_token(TokenType::DocComment {
+            literal: text.to_string(),
+        });
+    }
+
+    // block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.end() {
+                error::scan_error(self.line, "Unterminated block comment.");
+                self.has_err = true;
+                return;
+            }
+            match self.advance() {
+                '\n' => self.line += 1,
+                '/' if self.r#match('*') => depth += 1,
+                '*' if self.r#match('/') => depth -= 1,
+                _ => (),
+            }
+        }
+    }
+
     fn string(&mut self) {
         while !self.end() && self.peek() != '"' {
             // Lox supports multi-line string.
@@ -155,6 +263,7 @@ impl Scanner {
 
         if self.end() {
             error::scan_error(self.line, "Unterminated string.");
+            self.has_err = true;
             return;
         }
 
@@ -169,25 +278,113 @@ impl Scanner {
     }
 
     fn number(&mut self) {
-        while is_digit(self.peek()) {
-            self.advance();
+        // `0x`, `0o` and `0b` prefixes introduce hexadecimal, octal and binary integers.
+        if self.src[self.start..].starts_with('0') {
+            let radix = match self.peek() {
+                'x' | 'X' => 16,
+                'o' | 'O' => 8,
+                'b' | 'B' => 2,
+                _ => 10,
+            };
+            if radix != 10 && self.peek_next().is_digit(radix) {
+                self.advance();
+                self.digits(radix);
+                let digits = self.src[self.start + 2..self.cur].replace('_', "");
+                if self.suffix('n') {
+                    let literal = BigInt::from_digits(&digits, radix);
+                    self.add_token(TokenType::BIGINT { literal });
+                    return;
+                }
+                self.add_integer(&digits, radix);
+                return;
+            }
         }
 
+        self.digits(10);
+        let mut integer = true;
+
         // look for a fractional part.
         if self.peek() == '.' && is_digit(self.peek_next()) {
+            integer = false;
             self.advance();
-            while is_digit(self.peek()) {
+            self.digits(10);
+        }
+
+        // look for an exponent: `e` or `E`, an optional sign, and digits.
+        if matches!(self.peek(), 'e' | 'E') {
+            let sign = matches!(self.peek_next(), '+' | '-');
+            let first_digit = if sign {
+                self.src[self.cur..].chars().nth(2).unwrap_or('\0')
+            } else {
+                self.peek_next()
+            };
+            if is_digit(first_digit) {
+                integer = false;
                 self.advance();
+                if sign {
+                    self.advance();
+                }
+                self.digits(10);
+            }
+        }
+
+        let digits = self.src[self.start..self.cur].replace('_', "");
+        if integer && self.suffix('n') {
+            let literal = BigInt::from_digits(&digits, 10);
+            self.add_token(TokenType::BIGINT { literal });
+            return;
+        }
+        if self.suffix('d') {
+            match Decimal::parse(&digits) {
+                Some(literal) => self.add_token(TokenType::DECIMAL { literal }),
+                None => {
+                    error::scan_error(self.line, "Decimal exponent out of range.");
+                    self.has_err = true;
+                }
+            }
+            return;
+        }
+        if integer {
+            self.add_integer(&digits, 10);
+            return;
+        }
+        self.add_token(TokenType::NUMBER {
+            literal: F64(digits.parse::<f64>().unwrap()),
+        });
+    }
+
+    // a literal without a fractional part or exponent is an integer, it must fit in an i64.
+    // 2^63 is scanned as `i64::MIN`: it can only be written after a `-`, the parser checks that.
+    fn add_integer(&mut self, digits: &str, radix: u32) {
+        match u64::from_str_radix(digits, radix) {
+            Ok(n) if n <= i64::MAX as u64 + 1 => {
+                self.add_token(TokenType::INTEGER { literal: n as i64 })
             }
+            _ => {
+                error::scan_error(self.line, "Integer literal too large.");
+                self.has_err = true;
+            }
+        }
+    }
+
+    // a number ending with `n` is a bigint and one ending with `d` a decimal: `10n`, `1.20d`.
+    // consume the suffix if it's there and not the start of a longer word.
+    fn suffix(&mut self, suffix: char) -> bool {
+        if self.peek() == suffix && !is_alpha_numeric(self.peek_next()) {
+            self.advance();
+            return true;
         }
+        false
+    }
 
-        let num = self
-            .src
-            .get(self.start..self.cur)
-            .unwrap()
-            .parse::<f64>()
-            .unwrap();
-        self.add_token(TokenType::NUMBER { literal: F64(num) });
+    // consume the digits of a number in the given radix, a `_` is allowed between two digits:
+    // `1_000_000`, `0xFF_FF`.
+    fn digits(&mut self, radix: u32) {
+        while self.peek().is_digit(radix)
+            || (self.peek() == '_' && self.peek_next().is_digit(radix))
+        {
+            self.advance();
+        }
     }
 
     fn identifier(&mut self) {
@@ -200,16 +397,20 @@ impl Scanner {
 
     fn add_token(&mut self, t: TokenType) {
         let lexeme = self.src.get(self.start..self.cur).unwrap();
-        self.tokens.push(Token::new(t, lexeme, self.line));
+        self.tokens
+            .push(Token::new(t, lexeme, self.line, self.start));
     }
 }
 
 fn is_digit(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
 
+// identifiers may contain unicode letters, e.g. `var café = 1;`.
+// we use `char::is_alphabetic` (the Unicode `Alphabetic` property) as a simple subset of XID_Start,
+// unicode digits are not accepted, only ASCII `DIGIT`.
 fn is_alpha(c: char) -> bool {
-    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+    c.is_ascii_alphabetic() || c == '_' || (!c.is_ascii() && c.is_alphabetic())
 }
 
 fn is_alpha_numeric(c: char) -> bool {
@@ -1,5 +1,7 @@
 use core::fmt;
 
+use crate::bignum::{BigInt, Decimal};
+
 // Rust f64 不能直接实现 trait Eq & Hash, 所以封装一下.
 #[derive(Debug, Clone)]
 pub struct F64(pub f64);
@@ -21,10 +23,12 @@ impl std::hash::Hash for F64 {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TokenType {
     // Single-character tokens.
-    LeftParen,  // (
-    RightParen, // )
-    LeftBrace,  // {
-    RightBrace, // }
+    LeftParen,    // (
+    RightParen,   // )
+    LeftBrace,    // {
+    RightBrace,   // }
+    LeftBracket,  // [
+    RightBracket, // ]
     COMMA,
     DOT,
     MINUS,
@@ -32,6 +36,13 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
+    AMPERSAND,
+    PIPE,
+    CARET,
+    TILDE,
+    QUESTION,
+    COLON,
 
     // One or two character tokens.
     BANG,
@@ -42,30 +53,59 @@ pub enum TokenType {
     GreaterEqual,
     LESS,
     LessEqual,
+    LessLess,
+    GreaterGreater,
+    TildeSlash, // ~/
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    PlusPlus,
+    MinusMinus,
+    QuestionQuestion, // ??
+    QuestionDot,      // ?.
+    FatArrow,         // =>
+    DotDotDot,        // ...
 
     // Literals.
     // bool, nil 也能当字面量处理，这里把它们看成关键字
     IDENTIFIER,
     STRING { literal: String },
-    NUMBER { literal: F64 }, // all numbers in Lox are floating point at runtime.
+    INTEGER { literal: i64 },
+    BIGINT { literal: BigInt },     // `10n`
+    DECIMAL { literal: Decimal },   // `1.20d`
+    NUMBER { literal: F64 },        // floating point numbers.
+    DocComment { literal: String }, // `/// ...`, the parser attaches it to the next declaration.
 
     // Keywords.
     AND,
+    CASE,
+    CATCH,
     CLASS,
+    CONST,
     ELSE,
     FALSE,
+    FINALLY,
     FUN,
     FOR,
     IF,
+    IMPORT,
+    IN,
+    MATCH,
     NIL,
     OR,
     PRINT,
     RETURN,
     SUPER,
     THIS,
+    THROW,
+    TRAIT,
     TRUE,
+    TRY,
     VAR,
     WHILE,
+    YIELD,
     EOF,
 }
 
@@ -73,21 +113,32 @@ impl TokenType {
     pub fn keyword_or_id(s: &str) -> TokenType {
         match s {
             "and" => TokenType::AND,
+            "case" => TokenType::CASE,
+            "catch" => TokenType::CATCH,
             "class" => TokenType::CLASS,
+            "const" => TokenType::CONST,
             "else" => TokenType::ELSE,
             "false" => TokenType::FALSE,
+            "finally" => TokenType::FINALLY,
             "for" => TokenType::FOR,
             "fun" => TokenType::FUN,
             "if" => TokenType::IF,
+            "import" => TokenType::IMPORT,
+            "in" => TokenType::IN,
+            "match" => TokenType::MATCH,
             "nil" => TokenType::NIL,
             "or" => TokenType::OR,
             "print" => TokenType::PRINT,
             "return" => TokenType::RETURN,
             "super" => TokenType::SUPER,
             "this" => TokenType::THIS,
+            "throw" => TokenType::THROW,
+            "trait" => TokenType::TRAIT,
             "true" => TokenType::TRUE,
+            "try" => TokenType::TRY,
             "var" => TokenType::VAR,
             "while" => TokenType::WHILE,
+            "yield" => TokenType::YIELD,
             _ => TokenType::IDENTIFIER,
         }
     }
@@ -99,14 +150,18 @@ pub struct Token {
     pub t: TokenType,
     pub lexeme: String,
     pub line: u32, // location info
+    // the byte offset in the source, it tells apart two equal expressions on one line,
+    // e.g. the `i`s of `for (var i = 0; i < 3; i = i + 1) print i;` resolve to different scopes.
+    pub offset: usize,
 }
 
 impl Token {
-    pub fn new(t: TokenType, lexeme: &str, line: u32) -> Token {
+    pub fn new(t: TokenType, lexeme: &str, line: u32, offset: usize) -> Token {
         Token {
             t,
             lexeme: lexeme.to_string(),
             line,
+            offset,
         }
     }
 }
@@ -119,10 +174,26 @@ impl fmt::Display for TokenType {
             TokenType::RightParen => write!(f, "RIGHT_PAREN"),
             TokenType::LeftBrace => write!(f, "LEFT_BRACE"),
             TokenType::RightBrace => write!(f, "RIGHT_BRACE"),
+            TokenType::LeftBracket => write!(f, "LEFT_BRACKET"),
+            TokenType::RightBracket => write!(f, "RIGHT_BRACKET"),
             TokenType::BangEqual => write!(f, "BANG_EQUAL"),
             TokenType::EqualEqual => write!(f, "EQUAL_EQUAL"),
             TokenType::GreaterEqual => write!(f, "GREATER_EQUAL"),
             TokenType::LessEqual => write!(f, "LESS_EQUAL"),
+            TokenType::LessLess => write!(f, "LESS_LESS"),
+            TokenType::GreaterGreater => write!(f, "GREATER_GREATER"),
+            TokenType::TildeSlash => write!(f, "TILDE_SLASH"),
+            TokenType::PlusEqual => write!(f, "PLUS_EQUAL"),
+            TokenType::MinusEqual => write!(f, "MINUS_EQUAL"),
+            TokenType::StarEqual => write!(f, "STAR_EQUAL"),
+            TokenType::SlashEqual => write!(f, "SLASH_EQUAL"),
+            TokenType::PercentEqual => write!(f, "PERCENT_EQUAL"),
+            TokenType::PlusPlus => write!(f, "PLUS_PLUS"),
+            TokenType::MinusMinus => write!(f, "MINUS_MINUS"),
+            TokenType::QuestionQuestion => write!(f, "QUESTION_QUESTION"),
+            TokenType::QuestionDot => write!(f, "QUESTION_DOT"),
+            TokenType::FatArrow => write!(f, "FAT_ARROW"),
+            TokenType::DotDotDot => write!(f, "DOT_DOT_DOT"),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -132,6 +203,9 @@ impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.t {
             TokenType::STRING { literal } => write!(f, "STRING {} {}", &self.lexeme, literal),
+            TokenType::INTEGER { literal } => write!(f, "INTEGER {} {}", &self.lexeme, literal),
+            TokenType::BIGINT { literal } => write!(f, "BIGINT {} {}", &self.lexeme, literal),
+            TokenType::DECIMAL { literal } => write!(f, "DECIMAL {} {}", &self.lexeme, literal),
             TokenType::NUMBER { literal } => write!(f, "NUMBER {} {:?}", &self.lexeme, literal),
             TokenType::EOF => write!(f, "EOF null"),
             _ => write!(f, "{} {} null", self.t, &self.lexeme),
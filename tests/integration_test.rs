@@ -94,8 +94,13 @@ fn get_test_cases(case_dir: &str) -> Option<Vec<String>> {
 }
 
 fn run(case_dir: &str) {
-    let cases = get_test_cases(case_dir).unwrap_or(Vec::new());
-    if cases.len() == 0 {
+    run_with(case_dir, &[]);
+}
+
+// run the cases of the directory with the command line flags before the file, e.g. `--check`.
+fn run_with(case_dir: &str, flags: &[&str]) {
+    let cases = get_test_cases(case_dir).unwrap_or_default();
+    if cases.is_empty() {
         println!("No test cases in {}", case_dir);
         return;
     }
@@ -106,7 +111,7 @@ fn run(case_dir: &str) {
 
         let pb = PathBuf::from(path(&case));
         let expected = parse_comments(&pb);
-        let output = lox_command().arg(pb).output().unwrap();
+        let output = lox_command().args(flags).arg(pb).output().unwrap();
 
         let stdout: Vec<String> = String::from_utf8(output.stdout)
             .unwrap()
@@ -211,3 +216,138 @@ fn variable_test() {
 fn function_test() {
     run("function");
 }
+
+#[test]
+fn number_test() {
+    run("number");
+}
+
+#[test]
+fn integer_test() {
+    run("integer");
+}
+
+#[test]
+fn bignum_test() {
+    run("bignum");
+}
+
+#[test]
+fn exception_test() {
+    run("exception");
+}
+
+#[test]
+fn module_test() {
+    run("module");
+}
+
+#[test]
+fn field_test() {
+    run("field");
+}
+
+#[test]
+fn this_test() {
+    run("this");
+}
+
+#[test]
+fn constructor_test() {
+    run("constructor");
+}
+
+#[test]
+fn inheritance_test() {
+    run("inheritance");
+}
+
+#[test]
+fn conditional_test() {
+    run("conditional");
+}
+
+#[test]
+fn match_test() {
+    run("match");
+}
+
+#[test]
+fn for_in_test() {
+    run("for_in");
+}
+
+#[test]
+fn parameters_test() {
+    run("parameters");
+}
+
+#[test]
+fn const_test() {
+    run("const");
+}
+
+#[test]
+fn static_test() {
+    run("static");
+}
+
+#[test]
+fn trait_test() {
+    run("trait");
+}
+
+#[test]
+fn overload_test() {
+    run("overload");
+}
+
+#[test]
+fn generator_test() {
+    run("generator");
+}
+
+#[test]
+fn fiber_test() {
+    run("fiber");
+}
+
+#[test]
+fn annotation_test() {
+    run("annotation");
+}
+
+#[test]
+fn check_test() {
+    run_with("check", &["--check"]);
+}
+
+#[test]
+fn destructure_test() {
+    run("destructure");
+}
+
+#[test]
+fn tail_call_test() {
+    run("tail_call");
+}
+
+#[test]
+fn stack_test() {
+    run("stack");
+}
+
+#[test]
+fn max_depth_test() {
+    run_with("max_depth", &["--max-depth", "50"]);
+}
+
+#[test]
+fn execution_limit_test() {
+    run_with("execution_limit", &["--fuel", "10000"]);
+}
+
+#[test]
+fn memory_limit_test() {
+    run_with("memory_limit", &["--max-memory", "100000"]);
+}